@@ -0,0 +1,243 @@
+// Backups and exported documents, read without unpacking them first.
+
+use flate2::read::GzDecoder;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+
+use crate::source::Memory;
+
+/// The file is not an archive that can be read.
+#[derive(Debug)]
+pub struct UnsupportedArchive(pub PathBuf);
+
+impl Error for UnsupportedArchive {}
+
+impl fmt::Display for UnsupportedArchive {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?} is not a directory or an archive ending in .zip, .rmdoc, .tar, .tar.gz or .tgz",
+            self.0
+        )
+    }
+}
+
+/// The archive does not contain any documents.
+#[derive(Debug)]
+pub struct NoDocuments(pub PathBuf);
+
+impl Error for NoDocuments {}
+
+impl fmt::Display for NoDocuments {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "No documents found in {:?}", self.0)
+    }
+}
+
+/// The archive contains several directories with the same number of documents.
+#[derive(Debug)]
+pub struct AmbiguousRoot {
+    pub path: PathBuf,
+    /// Directories in the archive, `""` for the top level.
+    pub candidates: Vec<String>,
+}
+
+impl Error for AmbiguousRoot {}
+
+impl fmt::Display for AmbiguousRoot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let candidates: Vec<&str> = self
+            .candidates
+            .iter()
+            .map(|candidate| match candidate.as_str() {
+                "" => "/",
+                candidate => candidate,
+            })
+            .collect();
+        write!(
+            f,
+            "{:?} contains several xochitl directories with as many documents: {}",
+            self.path,
+            candidates.join(", ")
+        )
+    }
+}
+
+/// Whether `path` looks like an archive that `open_archive` can read.
+pub fn is_archive(path: &Path) -> bool {
+    archive_type(path).is_some()
+}
+
+enum ArchiveType {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+fn archive_type(path: &Path) -> Option<ArchiveType> {
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+    if name.ends_with(".zip") || name.ends_with(".rmdoc") {
+        Some(ArchiveType::Zip)
+    } else if name.ends_with(".tar") {
+        Some(ArchiveType::Tar)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(ArchiveType::TarGz)
+    } else {
+        None
+    }
+}
+
+/// Read the xochitl files in the archive at `path` into memory.
+///
+/// Zip files, including .rmdoc bundles, and tarballs, also compressed with gzip, are supported.
+/// The archive may contain the files of the xochitl directory or a directory above them; the
+/// directory with the most documents is taken.
+/// The PDF and EPUB files of imported documents are never read and therefore skipped.
+pub fn open_archive(path: &Path) -> Result<Memory, Box<dyn Error>> {
+    let files = match archive_type(path) {
+        Some(ArchiveType::Zip) => read_zip(File::open(path)?)?,
+        Some(ArchiveType::Tar) => read_tar(File::open(path)?)?,
+        Some(ArchiveType::TarGz) => read_tar(GzDecoder::new(File::open(path)?))?,
+        None => return Err(Box::new(UnsupportedArchive(path.to_owned()))),
+    };
+
+    let root = xochitl_root(&files).map_err(|candidates| -> Box<dyn Error> {
+        if candidates.is_empty() {
+            Box::new(NoDocuments(path.to_owned()))
+        } else {
+            Box::new(AmbiguousRoot {
+                path: path.to_owned(),
+                candidates,
+            })
+        }
+    })?;
+
+    let mut source = Memory::new();
+    for (name, data) in files {
+        if let Some(name) = name.strip_prefix(&root) {
+            source.insert(name, data);
+        }
+    }
+
+    Ok(source)
+}
+
+/// Paths and contents of the files in an archive.
+type Files = Vec<(String, Vec<u8>)>;
+
+/// The directory with the most metadata files, ending in `/` unless it is the top level.
+///
+/// Fails with the directories that have the most, none if there are no metadata files at all.
+fn xochitl_root(files: &[(String, Vec<u8>)]) -> Result<String, Vec<String>> {
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for (name, _) in files {
+        if name.ends_with(".metadata") {
+            let directory = match name.rfind('/') {
+                Some(position) => &name[..=position],
+                None => "",
+            };
+            *counts.entry(directory.to_owned()).or_default() += 1;
+        }
+    }
+
+    let most = counts.values().copied().max().unwrap_or(0);
+    let mut candidates: Vec<String> = counts
+        .into_iter()
+        .filter(|(_, count)| *count == most)
+        .map(|(directory, _)| directory)
+        .collect();
+    match candidates.len() {
+        1 => Ok(candidates.remove(0)),
+        _ => Err(candidates),
+    }
+}
+
+fn read_zip<Input: Read + Seek>(input: Input) -> Result<Files, Box<dyn Error>> {
+    let mut archive = zip::ZipArchive::new(input)?;
+    let mut files = vec![];
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let name = normalize(file.name());
+        if file.is_dir() || !is_needed(&name) {
+            continue;
+        }
+        let mut data = vec![];
+        file.read_to_end(&mut data)?;
+        files.push((name, data));
+    }
+
+    Ok(files)
+}
+
+fn read_tar<Input: Read>(input: Input) -> Result<Files, Box<dyn Error>> {
+    let mut archive = tar::Archive::new(input);
+    let mut files = vec![];
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = normalize(&entry.path()?.to_string_lossy());
+        if !entry.header().entry_type().is_file() || !is_needed(&name) {
+            continue;
+        }
+        let mut data = vec![];
+        entry.read_to_end(&mut data)?;
+        files.push((name, data));
+    }
+
+    Ok(files)
+}
+
+/// `name` with `/` as separator and without "./" components.
+fn normalize(name: &str) -> String {
+    name.replace('\\', "/")
+        .split('/')
+        .filter(|component| !component.is_empty() && *component != ".")
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Skip the largest files, which are never read. Thumbnails are small and kept for `thumbnail`.
+fn is_needed(name: &str) -> bool {
+    let lowercase = name.to_lowercase();
+    !(lowercase.ends_with(".pdf") || lowercase.ends_with(".epub") || lowercase.contains(".cache/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(names: &[&str]) -> Files {
+        names
+            .iter()
+            .map(|name| (name.to_string(), vec![]))
+            .collect()
+    }
+
+    #[test]
+    fn root_has_the_most_documents() {
+        let backup = files(&[
+            "backup/templates/x.metadata",
+            "backup/xochitl/a.metadata",
+            "backup/xochitl/a.content",
+            "backup/xochitl/b.metadata",
+        ]);
+        assert_eq!(xochitl_root(&backup), Ok("backup/xochitl/".to_owned()));
+        assert_eq!(
+            xochitl_root(&files(&["a.metadata", "a.content"])),
+            Ok(String::new())
+        );
+    }
+
+    #[test]
+    fn root_must_be_unique() {
+        let two = files(&["one/a.metadata", "two/b.metadata"]);
+        assert_eq!(
+            xochitl_root(&two),
+            Err(vec!["one/".to_owned(), "two/".to_owned()])
+        );
+        assert_eq!(xochitl_root(&files(&["a.content"])), Err(vec![]));
+    }
+}
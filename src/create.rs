@@ -0,0 +1,146 @@
+// Writing new notebooks from strokes, for forms and checklists that are prepared elsewhere.
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::error::Error;
+
+use crate::linefile::{Layer, LineFile};
+use crate::node::{Metadata, NodeType};
+use crate::page;
+use crate::source::{DocumentPaths, Source};
+
+/// The template of pages that do not name one.
+pub const BLANK_TEMPLATE: &str = "Blank";
+
+/// Version of the .lines format that is written.
+const LINES_VERSION: i32 = 5;
+
+/// A notebook to create, as read by `restorable new-notebook --from`:
+///
+/// ```json
+/// {"pages": [{"template": "P Lines small", "layers": [{"strokes": [...]}]}]}
+/// ```
+///
+/// Strokes have the same form as in the output of `restorable dump`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct NotebookSpec {
+    pub pages: Vec<PageSpec>,
+}
+
+impl NotebookSpec {
+    /// `count` empty pages with `template`.
+    pub fn blank(count: usize, template: &str) -> Self {
+        Self {
+            pages: vec![
+                PageSpec {
+                    template: template.to_owned(),
+                    layers: vec![],
+                };
+                count
+            ],
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PageSpec {
+    /// Name of a template on the device, like "Blank" or "P Grid medium"
+    #[serde(default = "blank_template")]
+    pub template: String,
+    /// An empty list gives a single empty layer
+    #[serde(default)]
+    pub layers: Vec<Layer>,
+}
+
+fn blank_template() -> String {
+    BLANK_TEMPLATE.to_owned()
+}
+
+/// Write a new notebook named `name` into folder `parent` (see `import::parent_id`).
+/// Returns the ID of the notebook.
+pub fn create_notebook(
+    source: &dyn Source,
+    name: &str,
+    parent: &str,
+    spec: &NotebookSpec,
+) -> Result<String, Box<dyn Error>> {
+    if spec.pages.is_empty() {
+        return Err("A notebook needs at least one page".into());
+    }
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let paths = DocumentPaths::new(&id);
+    source.create_dir(&paths.pages())?;
+
+    let mut page_ids = vec![];
+    let mut templates = String::new();
+    for spec in &spec.pages {
+        let page_id = uuid::Uuid::new_v4().to_string();
+        let mut layers = spec.layers.clone();
+        if layers.is_empty() {
+            layers.push(Layer { strokes: vec![] });
+        }
+        let metadata = page::Metadata {
+            layers: (1..=layers.len())
+                .map(|number| page::Layer {
+                    name: format!("Layer {}", number),
+                })
+                .collect(),
+        };
+        let linefile = LineFile {
+            version: LINES_VERSION,
+            layers,
+        };
+
+        let mut data = vec![];
+        linefile.write(&mut data)?;
+        source.write(&paths.page(&page_id), &data)?;
+        source.write(
+            &paths.page_metadata(&page_id),
+            &serde_json::to_vec_pretty(&metadata)?,
+        )?;
+        templates.push_str(&spec.template);
+        templates.push('\n');
+        page_ids.push(page_id);
+    }
+
+    // The fields the device writes for a new notebook
+    let content = json!({
+        "coverPageNumber": 0,
+        "extraMetadata": {},
+        "fileType": "notebook",
+        "fontName": "",
+        "lineHeight": -1,
+        "margins": 100,
+        "orientation": "portrait",
+        "pageCount": page_ids.len(),
+        "pages": page_ids,
+        "textScale": 1,
+        "transform": {
+            "m11": 1, "m12": 0, "m13": 0,
+            "m21": 0, "m22": 1, "m23": 0,
+            "m31": 0, "m32": 0, "m33": 1
+        }
+    });
+    let metadata = Metadata::new(NodeType::DocumentType, name, parent);
+    write_document(source, &id, templates.as_bytes(), &content, &metadata)?;
+
+    Ok(id)
+}
+
+/// Write the page data, content and metadata files of document `id`, once its pages or
+/// PDF are in place. The metadata goes last, because a document without it is ignored.
+pub(crate) fn write_document<C: Serialize>(
+    source: &dyn Source,
+    id: &str,
+    pagedata: &[u8],
+    content: &C,
+    metadata: &Metadata,
+) -> Result<(), Box<dyn Error>> {
+    let paths = DocumentPaths::new(id);
+    source.write(&paths.pagedata(), pagedata)?;
+    source.write(&paths.content(), &serde_json::to_vec_pretty(content)?)?;
+    source.write(&paths.metadata(), &serde_json::to_vec_pretty(metadata)?)?;
+
+    Ok(())
+}
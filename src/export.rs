@@ -0,0 +1,345 @@
+use std::error::Error;
+use std::fmt;
+#[cfg(any(feature = "pdf", feature = "svg", feature = "raster"))]
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::geometry::BoundingBox;
+use crate::notebook::Notebook;
+#[cfg(feature = "pdf")]
+use crate::outline::{add_outline, OutlineItem};
+use crate::page::Page;
+use crate::style::RenderOptions;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    Pdf,
+    Svg,
+    Png,
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pdf" => Ok(ImageFormat::Pdf),
+            "svg" => Ok(ImageFormat::Svg),
+            "png" => Ok(ImageFormat::Png),
+            _ => Err(format!(
+                "Invalid format '{}', expected 'pdf', 'svg' or 'png'",
+                s
+            )),
+        }
+    }
+}
+
+impl ImageFormat {
+    /// Guess the format from the extension of `path`.
+    pub fn from_path(path: &Path) -> Option<ImageFormat> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Pdf => "pdf",
+            ImageFormat::Svg => "svg",
+            ImageFormat::Png => "png",
+        }
+    }
+
+    /// Whether every page goes into its own file.
+    pub fn is_single_page(&self) -> bool {
+        *self != ImageFormat::Pdf
+    }
+
+    fn feature(&self) -> &'static str {
+        match self {
+            ImageFormat::Pdf => "pdf",
+            ImageFormat::Svg => "svg",
+            ImageFormat::Png => "raster",
+        }
+    }
+}
+
+/// The format was disabled at compile time.
+#[derive(Debug)]
+pub struct MissingFeature(pub ImageFormat);
+
+impl Error for MissingFeature {}
+
+impl fmt::Display for MissingFeature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} output is not available, restorable was built without the '{}' feature",
+            self.0.extension().to_uppercase(),
+            self.0.feature()
+        )
+    }
+}
+
+/// Files written for the pages with `page_numbers`, if the notebook is exported to `output_path`.
+///
+/// Formats with one page per file, and all formats if `split` is set, get a page number
+/// in the file name, unless there is only a single page.
+pub fn output_paths(
+    format: ImageFormat,
+    output_path: &Path,
+    page_numbers: &[usize],
+    split: bool,
+) -> Vec<PathBuf> {
+    if !(format.is_single_page() || split) || page_numbers.len() == 1 {
+        return vec![output_path.to_owned()];
+    }
+
+    let stem = output_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    page_numbers
+        .iter()
+        .map(|number| {
+            output_path.with_file_name(format!("{}-{}.{}", stem, number, format.extension()))
+        })
+        .collect()
+}
+
+/// Write `notebook` to `output_path` and return the files written.
+///
+/// With `split`, every page goes into its own file, also for PDF.
+pub fn export_notebook(
+    notebook: Notebook,
+    format: ImageFormat,
+    output_path: &Path,
+    split: bool,
+    options: &RenderOptions,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let paths = output_paths(format, output_path, &notebook.page_numbers, split);
+
+    match format {
+        ImageFormat::Pdf => export_pdf(notebook, &paths, options)?,
+        ImageFormat::Svg => export_svg(notebook, &paths, options)?,
+        ImageFormat::Png => export_png(notebook, &paths, options)?,
+    }
+
+    Ok(paths)
+}
+
+/// Render `page` into a file in memory, cropped like `export_notebook` would.
+pub fn render_image(
+    page: Page,
+    format: ImageFormat,
+    options: &RenderOptions,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let frame = options.frames(std::slice::from_ref(&page))[0];
+    let mut image = vec![];
+    match format {
+        ImageFormat::Pdf => pdf_image(page, &frame, &mut image, options)?,
+        ImageFormat::Svg => svg_image(page, &frame, &mut image, options)?,
+        ImageFormat::Png => png_image(page, &frame, &mut image, options)?,
+    }
+
+    Ok(image)
+}
+
+#[cfg(feature = "pdf")]
+fn pdf_image(
+    page: Page,
+    frame: &BoundingBox,
+    image: &mut Vec<u8>,
+    options: &RenderOptions,
+) -> Result<(), Box<dyn Error>> {
+    crate::render::render_pages(vec![page], &[*frame], &[], image, options)?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "pdf"))]
+fn pdf_image(
+    _page: Page,
+    _frame: &BoundingBox,
+    _image: &mut Vec<u8>,
+    _options: &RenderOptions,
+) -> Result<(), Box<dyn Error>> {
+    Err(Box::new(MissingFeature(ImageFormat::Pdf)))
+}
+
+#[cfg(feature = "svg")]
+fn svg_image(
+    page: Page,
+    frame: &BoundingBox,
+    image: &mut Vec<u8>,
+    options: &RenderOptions,
+) -> Result<(), Box<dyn Error>> {
+    crate::svg::render_page_svg(&page, frame, image, options)?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "svg"))]
+fn svg_image(
+    _page: Page,
+    _frame: &BoundingBox,
+    _image: &mut Vec<u8>,
+    _options: &RenderOptions,
+) -> Result<(), Box<dyn Error>> {
+    Err(Box::new(MissingFeature(ImageFormat::Svg)))
+}
+
+#[cfg(feature = "raster")]
+fn png_image(
+    page: Page,
+    frame: &BoundingBox,
+    image: &mut Vec<u8>,
+    options: &RenderOptions,
+) -> Result<(), Box<dyn Error>> {
+    crate::render::render_page_png(page, frame, image, options)?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "raster"))]
+fn png_image(
+    _page: Page,
+    _frame: &BoundingBox,
+    _image: &mut Vec<u8>,
+    _options: &RenderOptions,
+) -> Result<(), Box<dyn Error>> {
+    Err(Box::new(MissingFeature(ImageFormat::Png)))
+}
+
+#[cfg(feature = "pdf")]
+fn export_pdf(
+    notebook: Notebook,
+    paths: &[PathBuf],
+    options: &RenderOptions,
+) -> Result<(), Box<dyn Error>> {
+    if let [path] = paths {
+        let mut file = File::create(path)?;
+        crate::render::render_notebook(notebook, &mut file, options)?;
+        return Ok(());
+    }
+
+    // Computed for all pages together, so "--crop notebook" still gives every file the same size
+    let frames = options.frames(&notebook.pages);
+    for ((page, frame), path) in notebook.pages.into_iter().zip(frames).zip(paths.iter()) {
+        let mut file = File::create(path)?;
+        crate::render::render_pages(vec![page], &[frame], &[], &mut file, options)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "pdf"))]
+fn export_pdf(
+    _notebook: Notebook,
+    _paths: &[PathBuf],
+    _options: &RenderOptions,
+) -> Result<(), Box<dyn Error>> {
+    Err(Box::new(MissingFeature(ImageFormat::Pdf)))
+}
+
+/// A notebook in a combined PDF.
+pub struct Part {
+    /// Folders the notebook is in, below the exported folder.
+    pub folders: Vec<String>,
+    pub title: String,
+    pub notebook: Notebook,
+}
+
+/// Write all `parts` into one PDF, with bookmarks for folders, notebooks and pages.
+#[cfg(feature = "pdf")]
+pub fn export_combined(
+    parts: Vec<Part>,
+    output_path: &Path,
+    options: &RenderOptions,
+) -> Result<(), Box<dyn Error>> {
+    let mut pages = vec![];
+    let mut frames = vec![];
+    let mut destinations = vec![];
+    let mut outline = vec![];
+    for part in parts {
+        let mut item = OutlineItem::new(&part.title, None);
+        for number in &part.notebook.page_numbers {
+            let destination = format!("page{}", destinations.len() + 1);
+            let title = format!("Page {}", number);
+            item.children
+                .push(OutlineItem::new(&title, Some(destination.clone())));
+            destinations.push(destination);
+        }
+        item.destination = item
+            .children
+            .first()
+            .and_then(|page| page.destination.clone());
+        OutlineItem::insert(&mut outline, &part.folders, item);
+
+        frames.extend(options.frames(&part.notebook.pages));
+        pages.extend(part.notebook.pages);
+    }
+
+    let mut pdf = vec![];
+    crate::render::render_pages(pages, &frames, &destinations, &mut pdf, options)?;
+    add_outline(&mut pdf, &outline)?;
+    std::fs::write(output_path, pdf)?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "pdf"))]
+pub fn export_combined(
+    _parts: Vec<Part>,
+    _output_path: &Path,
+    _options: &RenderOptions,
+) -> Result<(), Box<dyn Error>> {
+    Err(Box::new(MissingFeature(ImageFormat::Pdf)))
+}
+
+#[cfg(feature = "svg")]
+fn export_svg(
+    notebook: Notebook,
+    paths: &[PathBuf],
+    options: &RenderOptions,
+) -> Result<(), Box<dyn Error>> {
+    let frames = options.frames(&notebook.pages);
+    for ((page, frame), path) in notebook.pages.iter().zip(frames).zip(paths.iter()) {
+        let mut file = File::create(path)?;
+        crate::svg::render_page_svg(page, &frame, &mut file, options)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "svg"))]
+fn export_svg(
+    _notebook: Notebook,
+    _paths: &[PathBuf],
+    _options: &RenderOptions,
+) -> Result<(), Box<dyn Error>> {
+    Err(Box::new(MissingFeature(ImageFormat::Svg)))
+}
+
+#[cfg(feature = "raster")]
+fn export_png(
+    notebook: Notebook,
+    paths: &[PathBuf],
+    options: &RenderOptions,
+) -> Result<(), Box<dyn Error>> {
+    let frames = options.frames(&notebook.pages);
+    for ((page, frame), path) in notebook.pages.into_iter().zip(frames).zip(paths.iter()) {
+        let mut file = File::create(path)?;
+        crate::render::render_page_png(page, &frame, &mut file, options)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "raster"))]
+fn export_png(
+    _notebook: Notebook,
+    _paths: &[PathBuf],
+    _options: &RenderOptions,
+) -> Result<(), Box<dyn Error>> {
+    Err(Box::new(MissingFeature(ImageFormat::Png)))
+}
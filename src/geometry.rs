@@ -0,0 +1,205 @@
+use serde::Serialize;
+
+use crate::linefile::{Layer, LineFile, Segment, Stroke};
+use crate::page::Page;
+
+/// Axis-aligned rectangle in device coordinates.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct BoundingBox {
+    pub min_x: f32,
+    pub min_y: f32,
+    pub max_x: f32,
+    pub max_y: f32,
+}
+
+impl BoundingBox {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self {
+            min_x: x,
+            min_y: y,
+            max_x: x + width,
+            max_y: y + height,
+        }
+    }
+
+    pub fn width(&self) -> f32 {
+        self.max_x - self.min_x
+    }
+
+    pub fn height(&self) -> f32 {
+        self.max_y - self.min_y
+    }
+
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        self.min_x <= x && x <= self.max_x && self.min_y <= y && y <= self.max_y
+    }
+
+    pub fn intersects(&self, other: &BoundingBox) -> bool {
+        self.min_x <= other.max_x
+            && other.min_x <= self.max_x
+            && self.min_y <= other.max_y
+            && other.min_y <= self.max_y
+    }
+
+    /// Smallest box containing both boxes.
+    pub fn union(&self, other: &BoundingBox) -> BoundingBox {
+        BoundingBox {
+            min_x: self.min_x.min(other.min_x),
+            min_y: self.min_y.min(other.min_y),
+            max_x: self.max_x.max(other.max_x),
+            max_y: self.max_y.max(other.max_y),
+        }
+    }
+
+    /// Grow the box by `margin` on every side.
+    pub fn expand(&self, margin: f32) -> BoundingBox {
+        BoundingBox {
+            min_x: self.min_x - margin,
+            min_y: self.min_y - margin,
+            max_x: self.max_x + margin,
+            max_y: self.max_y + margin,
+        }
+    }
+
+    fn around(x: f32, y: f32) -> BoundingBox {
+        BoundingBox {
+            min_x: x,
+            min_y: y,
+            max_x: x,
+            max_y: y,
+        }
+    }
+}
+
+/// Union of all boxes, `None` if there are none.
+pub(crate) fn union_all<I: Iterator<Item = BoundingBox>>(boxes: I) -> Option<BoundingBox> {
+    boxes.fold(None, |result, bounding_box| match result {
+        None => Some(bounding_box),
+        Some(result) => Some(result.union(&bounding_box)),
+    })
+}
+
+impl Stroke {
+    /// Box around the points of the stroke, not including its width.
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        union_all(
+            self.segments
+                .iter()
+                .map(|segment| BoundingBox::around(segment.x, segment.y)),
+        )
+    }
+
+    /// Length of the line through all points.
+    pub fn length(&self) -> f32 {
+        self.segments
+            .windows(2)
+            .map(|pair| distance(pair[0].x, pair[0].y, pair[1].x, pair[1].y))
+            .sum()
+    }
+
+    /// Shortest distance between the point and the line through the stroke's points.
+    pub fn distance_to(&self, x: f32, y: f32) -> Option<f32> {
+        match self.segments.len() {
+            0 => None,
+            1 => Some(distance(x, y, self.segments[0].x, self.segments[0].y)),
+            _ => self
+                .segments
+                .windows(2)
+                .map(|pair| distance_to_line(x, y, &pair[0], &pair[1]))
+                .fold(None, |min: Option<f32>, d| {
+                    Some(min.map_or(d, |min| min.min(d)))
+                }),
+        }
+    }
+
+    /// Remove points that deviate less than `tolerance` from the simplified line,
+    /// using the Ramer–Douglas–Peucker algorithm.
+    pub fn simplify(&self, tolerance: f32) -> Stroke {
+        let mut keep = vec![false; self.segments.len()];
+        if !keep.is_empty() {
+            let last = keep.len() - 1;
+            keep[0] = true;
+            keep[last] = true;
+            mark_kept(&self.segments, 0, last, tolerance, &mut keep);
+        }
+
+        Stroke {
+            segments: self
+                .segments
+                .iter()
+                .zip(keep.iter())
+                .filter(|(_, keep)| **keep)
+                .map(|(segment, _)| segment.clone())
+                .collect(),
+            ..self.clone()
+        }
+    }
+}
+
+fn mark_kept(segments: &[Segment], first: usize, last: usize, tolerance: f32, keep: &mut [bool]) {
+    if last <= first + 1 {
+        return;
+    }
+
+    let (mut farthest, mut max_distance) = (first, 0.0);
+    for i in first + 1..last {
+        let d = distance_to_line(
+            segments[i].x,
+            segments[i].y,
+            &segments[first],
+            &segments[last],
+        );
+        if d > max_distance {
+            farthest = i;
+            max_distance = d;
+        }
+    }
+
+    if max_distance > tolerance {
+        keep[farthest] = true;
+        mark_kept(segments, first, farthest, tolerance, keep);
+        mark_kept(segments, farthest, last, tolerance, keep);
+    }
+}
+
+fn distance(x1: f32, y1: f32, x2: f32, y2: f32) -> f32 {
+    ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt()
+}
+
+/// Distance between the point and the line segment from `a` to `b`.
+fn distance_to_line(x: f32, y: f32, a: &Segment, b: &Segment) -> f32 {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length_squared = dx * dx + dy * dy;
+    if length_squared == 0.0 {
+        return distance(x, y, a.x, a.y);
+    }
+    let t = (((x - a.x) * dx + (y - a.y) * dy) / length_squared).clamp(0.0, 1.0);
+
+    distance(x, y, a.x + t * dx, a.y + t * dy)
+}
+
+impl Layer {
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        union_all(self.strokes.iter().filter_map(Stroke::bounding_box))
+    }
+
+    /// Strokes that pass within `tolerance` of the point.
+    pub fn hit_test(&self, x: f32, y: f32, tolerance: f32) -> Vec<&Stroke> {
+        self.strokes
+            .iter()
+            .filter(|stroke| matches!(stroke.distance_to(x, y), Some(distance) if distance <= tolerance))
+            .collect()
+    }
+}
+
+impl LineFile {
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        union_all(self.layers.iter().filter_map(Layer::bounding_box))
+    }
+}
+
+impl Page {
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        self.linefile.bounding_box()
+    }
+}
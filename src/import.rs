@@ -0,0 +1,87 @@
+// Adding PDFs and EPUBs to the xochitl directory, the way the desktop app transfers them.
+
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::create::write_document;
+use crate::node::{Metadata, Node, NodeType};
+use crate::notebook::Content;
+use crate::source::{DocumentPaths, Source};
+
+/// The file is neither a PDF nor an EPUB.
+#[derive(Debug)]
+pub struct UnsupportedFile(pub PathBuf);
+
+impl Error for UnsupportedFile {}
+
+impl fmt::Display for UnsupportedFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Cannot import {:?}, only .pdf and .epub files", self.0)
+    }
+}
+
+/// Documents can only be placed in folders.
+#[derive(Debug)]
+pub struct NotAFolder(pub String);
+
+impl Error for NotAFolder {}
+
+impl fmt::Display for NotAFolder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "'{}' is not a folder", self.0)
+    }
+}
+
+/// The value of `Metadata::parent` for documents placed in `folder`.
+pub fn parent_id(folder: &Node) -> Result<&str, NotAFolder> {
+    match folder.id.as_str() {
+        Node::ROOT_ID => Ok(""),
+        // Not a real folder, only a place for documents whose parent is missing
+        Node::LOST_AND_FOUND_ID => Err(NotAFolder(folder.name().to_owned())),
+        id if folder.metadata.is_some() && !folder.is_notebook() => Ok(id),
+        _ => Err(NotAFolder(folder.name().to_owned())),
+    }
+}
+
+/// Copy the PDF or EPUB `data` into `source` as a new document in folder `parent` (see
+/// `parent_id`), named `name` or after `file_path`. Returns the ID of the document.
+///
+/// The device fills in the page list and thumbnails when it opens the document for the first
+/// time. xochitl must be restarted to notice documents that were added while it was running.
+pub fn import_document(
+    source: &dyn Source,
+    file_path: &Path,
+    data: &[u8],
+    parent: &str,
+    name: Option<&str>,
+) -> Result<String, Box<dyn Error>> {
+    let file_type = match file_path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+    {
+        Some(extension) if extension == "pdf" || extension == "epub" => extension,
+        _ => return Err(Box::new(UnsupportedFile(file_path.to_owned()))),
+    };
+    let name = match name {
+        Some(name) => name.to_owned(),
+        None => file_path
+            .file_stem()
+            .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned()),
+    };
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let paths = DocumentPaths::new(&id);
+    let content = Content {
+        file_type: file_type.clone(),
+        pages: vec![],
+        c_pages: None,
+    };
+    let metadata = Metadata::new(NodeType::DocumentType, &name, parent);
+
+    source.write(&paths.document(&file_type), data)?;
+    source.create_dir(&paths.pages())?;
+    write_document(source, &id, b"", &content, &metadata)?;
+
+    Ok(id)
+}
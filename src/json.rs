@@ -1,10 +1,11 @@
 use serde::de::DeserializeOwned;
 use std::error::Error;
-use std::path::Path;
 
-pub fn parse<T: DeserializeOwned>(path: &Path) -> Result<T, Box<dyn Error>> {
-    let json_str = std::fs::read_to_string(path)?;
-    let content: T = serde_json::from_str(&json_str)?;
+use crate::source::Source;
+
+pub fn parse<T: DeserializeOwned>(source: &dyn Source, path: &str) -> Result<T, Box<dyn Error>> {
+    let data = source.read(path)?;
+    let content: T = serde_json::from_slice(&data)?;
 
     Ok(content)
 }
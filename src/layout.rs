@@ -0,0 +1,256 @@
+// Where pages go on printed sheets.
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::geometry::BoundingBox;
+
+/// Resolution of the device's display.
+pub const DPI: f32 = 226.0;
+
+/// Size of a device pixel in PDF points, so pages print at their real size.
+pub const POINTS_PER_PIXEL: f32 = 72.0 / DPI;
+
+const POINTS_PER_MM: f32 = 72.0 / 25.4;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Paper {
+    /// The size of the page on the device.
+    #[default]
+    Device,
+    A4,
+    Letter,
+    A5,
+}
+
+impl Paper {
+    /// Width and height in points, in portrait orientation.
+    fn size(&self) -> Option<(f32, f32)> {
+        match self {
+            Paper::Device => None,
+            Paper::A4 => Some((595.28, 841.89)),
+            Paper::Letter => Some((612.0, 792.0)),
+            Paper::A5 => Some((419.53, 595.28)),
+        }
+    }
+}
+
+impl FromStr for Paper {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "device" => Ok(Paper::Device),
+            "a4" => Ok(Paper::A4),
+            "letter" => Ok(Paper::Letter),
+            "a5" => Ok(Paper::A5),
+            _ => Err(format!(
+                "Invalid paper '{}', expected 'device', 'a4', 'letter' or 'a5'",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Layout {
+    pub paper: Paper,
+    /// Space around and between pages in millimeters, ignored for device paper.
+    pub margin: f32,
+    /// Pages per sheet: 1, 2 or 4.
+    pub pages_per_sheet: usize,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            paper: Paper::Device,
+            margin: 10.0,
+            pages_per_sheet: 1,
+        }
+    }
+}
+
+/// A page on a sheet: the part of the page inside `frame` is drawn at `x`, `y`, scaled by `scale`.
+#[derive(Clone, Copy, Debug)]
+pub struct Placement {
+    pub frame: BoundingBox,
+    pub x: f32,
+    pub y: f32,
+    pub scale: f32,
+}
+
+/// One page of the output, sizes in points.
+#[derive(Clone, Debug)]
+pub struct Sheet {
+    pub width: f32,
+    pub height: f32,
+    pub placements: Vec<Placement>,
+}
+
+/// The paper margin leaves no room for the pages.
+#[derive(Debug)]
+pub struct MarginTooLarge {
+    pub margin: f32,
+}
+
+impl Error for MarginTooLarge {}
+
+impl fmt::Display for MarginTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "A paper margin of {} mm leaves no room for the pages",
+            self.margin
+        )
+    }
+}
+
+impl Layout {
+    /// Check that the pages fit between the margins.
+    pub fn validate(&self) -> Result<(), MarginTooLarge> {
+        let (columns, rows) = self.grid();
+        if let Some((width, height)) = self.paper_size() {
+            let margin = self.margin.max(0.0) * POINTS_PER_MM;
+            if margin * (columns + 1) as f32 >= width || margin * (rows + 1) as f32 >= height {
+                return Err(MarginTooLarge {
+                    margin: self.margin,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Distribute pages with the given frames over sheets, in order.
+    pub fn sheets(&self, frames: &[BoundingBox]) -> Vec<Sheet> {
+        let (columns, rows) = self.grid();
+        frames
+            .chunks(columns * rows)
+            .map(|frames| match self.paper_size() {
+                None => self.device_sheet(frames, columns),
+                Some((width, height)) => self.paper_sheet(frames, width, height, columns, rows),
+            })
+            .collect()
+    }
+
+    /// Columns and rows of pages on a sheet.
+    fn grid(&self) -> (usize, usize) {
+        match self.pages_per_sheet {
+            2 => (2, 1),
+            4 => (2, 2),
+            _ => (1, 1),
+        }
+    }
+
+    /// Width and height of the sheets in points, `None` for device paper.
+    fn paper_size(&self) -> Option<(f32, f32)> {
+        let (width, height) = self.paper.size()?;
+        let (columns, rows) = self.grid();
+        // Two pages side by side fit better on landscape paper
+        if columns > rows {
+            Some((height, width))
+        } else {
+            Some((width, height))
+        }
+    }
+
+    /// Pages at their real size, in a grid of cells as large as the largest page.
+    fn device_sheet(&self, frames: &[BoundingBox], columns: usize) -> Sheet {
+        let cell_width = frames.iter().map(|f| f.width()).fold(0.0, f32::max) * POINTS_PER_PIXEL;
+        let cell_height = frames.iter().map(|f| f.height()).fold(0.0, f32::max) * POINTS_PER_PIXEL;
+        let columns = columns.min(frames.len());
+        let rows = frames.len().div_ceil(columns);
+
+        let placements = frames
+            .iter()
+            .enumerate()
+            .map(|(i, frame)| Placement {
+                frame: *frame,
+                x: (i % columns) as f32 * cell_width,
+                y: (i / columns) as f32 * cell_height,
+                scale: POINTS_PER_PIXEL,
+            })
+            .collect();
+
+        Sheet {
+            width: cell_width * columns as f32,
+            height: cell_height * rows as f32,
+            placements,
+        }
+    }
+
+    /// Pages scaled to fit into equally sized cells on a sheet of paper, centered in their cell.
+    fn paper_sheet(
+        &self,
+        frames: &[BoundingBox],
+        width: f32,
+        height: f32,
+        columns: usize,
+        rows: usize,
+    ) -> Sheet {
+        let margin = self.margin.max(0.0) * POINTS_PER_MM;
+        let cell_width = (width - margin * (columns + 1) as f32) / columns as f32;
+        let cell_height = (height - margin * (rows + 1) as f32) / rows as f32;
+
+        let placements = frames
+            .iter()
+            .enumerate()
+            .map(|(i, frame)| {
+                let scale = (cell_width / frame.width()).min(cell_height / frame.height());
+                let cell_x = margin + (i % columns) as f32 * (cell_width + margin);
+                let cell_y = margin + (i / columns) as f32 * (cell_height + margin);
+                Placement {
+                    frame: *frame,
+                    x: cell_x + (cell_width - frame.width() * scale) / 2.0,
+                    y: cell_y + (cell_height - frame.height() * scale) / 2.0,
+                    scale,
+                }
+            })
+            .collect();
+
+        Sheet {
+            width,
+            height,
+            placements,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(paper: Paper, margin: f32, pages_per_sheet: usize) -> Layout {
+        Layout {
+            paper,
+            margin,
+            pages_per_sheet,
+        }
+    }
+
+    #[test]
+    fn margins_must_leave_room_for_pages() {
+        assert!(layout(Paper::A4, 10.0, 4).validate().is_ok());
+        // A5 is 148 mm wide, three margins of 50 mm do not fit
+        assert!(layout(Paper::A5, 50.0, 4).validate().is_err());
+        assert!(layout(Paper::A5, 50.0, 1).validate().is_ok());
+        assert!(layout(Paper::Device, 1000.0, 4).validate().is_ok());
+    }
+
+    #[test]
+    fn pages_are_scaled_to_fit_their_cell() {
+        let frame = BoundingBox::new(0.0, 0.0, 1404.0, 1874.0);
+        let sheets = layout(Paper::A4, 10.0, 2).sheets(&[frame, frame, frame]);
+
+        assert_eq!(sheets.len(), 2);
+        // Landscape for two pages side by side
+        assert!(sheets[0].width > sheets[0].height);
+        for placement in &sheets[0].placements {
+            assert!(placement.scale > 0.0);
+            assert!(placement.x >= 0.0 && placement.y >= 0.0);
+            assert!(placement.x + frame.width() * placement.scale <= sheets[0].width);
+        }
+    }
+}
@@ -0,0 +1,51 @@
+//! Parse and render the documents of the [reMarkable paper tablet](https://remarkable.com/).
+//!
+//! - [`source`] reads the files of a xochitl data directory, locally or over SFTP (feature `sftp`).
+//!   [`archive`] reads them from zip files and tarballs (feature `archive`).
+//! - [`node`] reads the folder structure of a xochitl data directory.
+//! - [`notebook`], [`page`] and [`linefile`] load a notebook and its strokes.
+//! - [`import`] adds PDFs and EPUBs to a xochitl data directory, [`organize`] creates, moves,
+//!   renames and deletes documents and folders (feature `write`).
+//! - [`create`] writes new notebooks from strokes (feature `write`), which [`vector`] reads from
+//!   SVG and PDF drawings (feature `vector`).
+//! - [`export`] writes notebooks as PDF (feature `pdf`), SVG (feature `svg`) or PNG (feature `raster`).
+//!   [`thumbnail`] writes page previews, copied from the device where it keeps them up to date.
+//!
+//! The `pdf` and `raster` features use Skia. Without default features, no output format is
+//! built, so there are no native dependencies; [`export`] then returns an error for every
+//! format. Add `svg` for output in pure Rust.
+
+#[cfg(feature = "archive")]
+pub mod archive;
+#[cfg(feature = "write")]
+pub mod create;
+pub mod export;
+pub mod geometry;
+#[cfg(feature = "write")]
+pub mod import;
+mod json;
+pub mod layout;
+pub mod linefile;
+pub mod node;
+pub mod notebook;
+#[cfg(feature = "write")]
+pub mod organize;
+pub mod outline;
+pub mod output;
+pub mod page;
+pub mod pages;
+#[cfg(any(feature = "pdf", feature = "raster"))]
+pub mod render;
+pub mod scene;
+pub mod search;
+pub mod selector;
+#[cfg(feature = "sftp")]
+pub mod sftp;
+pub mod source;
+pub mod style;
+#[cfg(feature = "svg")]
+pub mod svg;
+pub mod text;
+pub mod thumbnail;
+#[cfg(feature = "vector")]
+pub mod vector;
@@ -1,13 +1,17 @@
 // See https://remarkablewiki.com/tech/filesystem
 
+use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
 use std::error::Error;
 use std::fmt;
 use std::fs::File;
 use std::io::prelude::Read;
-use std::io::{BufReader, Bytes};
+use std::io::{BufReader, ErrorKind, Write};
+use std::str::FromStr;
 use std::{i32, str};
 
+const HEADER: &str = "reMarkable .lines file, version=";
+
 #[derive(Debug)]
 pub struct ParseError {
     pub message: String,
@@ -56,29 +60,40 @@ where
     Self: std::marker::Sized,
 {
     // Read the number of items, then parse a vector
-    fn parse_from(version: i32, bytes: &mut Bytes<BufReader<File>>) -> Result<Self, ParseError>;
+    fn parse_from<Input: Read>(version: i32, bytes: &mut Input) -> Result<Self, ParseError>;
 }
 
-#[derive(Debug)]
+trait WriteTo {
+    // Counterpart of ParseFrom
+    fn write_to<Output: Write>(&self, version: i32, output: &mut Output) -> std::io::Result<()>;
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct LineFile {
     pub version: i32,
     pub layers: Vec<Layer>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Layer {
     pub strokes: Vec<Stroke>,
 }
 
 impl ParseFrom for Layer {
-    fn parse_from(version: i32, bytes: &mut Bytes<BufReader<File>>) -> Result<Layer, ParseError> {
+    fn parse_from<Input: Read>(version: i32, bytes: &mut Input) -> Result<Layer, ParseError> {
         let strokes = parse_multiple(version, bytes)?;
 
         Ok(Layer { strokes })
     }
 }
 
-#[derive(Debug)]
+impl WriteTo for Layer {
+    fn write_to<Output: Write>(&self, version: i32, output: &mut Output) -> std::io::Result<()> {
+        write_multiple(version, &self.strokes, output)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum Pen {
     // Based on https://github.com/ax3l/lines-are-rusty/blob/develop/src/lib.rs
     BallPoint,
@@ -130,7 +145,68 @@ impl TryFrom<u32> for Pen {
     }
 }
 
-#[derive(Debug)]
+impl FromStr for Pen {
+    type Err = String;
+
+    /// The drawing tools, like "fineliner" or "mechanical-pencil".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace(&['-', '_'][..], "").as_str() {
+            "ballpoint" => Ok(Pen::BallPoint),
+            "marker" => Ok(Pen::Marker),
+            "fineliner" => Ok(Pen::Fineliner),
+            "pencil" => Ok(Pen::Pencil),
+            "mechanicalpencil" => Ok(Pen::MechanicalPencil),
+            "paintbrush" => Ok(Pen::PaintBrush),
+            "highlighter" => Ok(Pen::Highlighter),
+            "calligraphy" => Ok(Pen::Calligraphy),
+            "pen" => Ok(Pen::Pen),
+            _ => Err(format!(
+                "Invalid pen '{}', expected 'ballpoint', 'marker', 'fineliner', 'pencil', \
+                 'mechanical-pencil', 'paintbrush', 'highlighter', 'calligraphy' or 'pen'",
+                s
+            )),
+        }
+    }
+}
+
+impl Pen {
+    /// Value stored in a file of the given version
+    fn code(&self, version: i32) -> u32 {
+        if version >= 5 {
+            match self {
+                Pen::PaintBrush => 12,
+                Pen::MechanicalPencil => 13,
+                Pen::Pencil => 14,
+                Pen::BallPoint => 15,
+                Pen::Marker => 16,
+                Pen::Fineliner => 17,
+                Pen::Highlighter => 18,
+                Pen::Eraser => 6,
+                Pen::EraseArea => 8,
+                Pen::EraseAll => 9,
+                Pen::SelectionBrush => 10,
+                Pen::Calligraphy => 21,
+                Pen::Pen => 2,
+            }
+        } else {
+            match self {
+                Pen::PaintBrush => 0,
+                Pen::Pencil => 1,
+                Pen::BallPoint | Pen::Pen | Pen::Calligraphy => 2,
+                Pen::Marker => 3,
+                Pen::Fineliner => 4,
+                Pen::Highlighter => 5,
+                Pen::Eraser => 6,
+                Pen::MechanicalPencil => 7,
+                Pen::EraseArea => 8,
+                Pen::EraseAll => 9,
+                Pen::SelectionBrush => 10,
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum Color {
     Black,
     Gray,
@@ -153,7 +229,33 @@ impl TryFrom<u32> for Color {
     }
 }
 
-#[derive(Debug)]
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "black" => Ok(Color::Black),
+            "gray" | "grey" => Ok(Color::Gray),
+            "white" => Ok(Color::White),
+            _ => Err(format!(
+                "Invalid color '{}', expected 'black', 'gray' or 'white'",
+                s
+            )),
+        }
+    }
+}
+
+impl Color {
+    fn code(&self) -> u32 {
+        match self {
+            Color::Black => 0,
+            Color::Gray => 1,
+            Color::White => 2,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Stroke {
     pub pen: Pen,
     pub color: Color,
@@ -162,7 +264,7 @@ pub struct Stroke {
 }
 
 impl ParseFrom for Stroke {
-    fn parse_from(version: i32, bytes: &mut Bytes<BufReader<File>>) -> Result<Stroke, ParseError> {
+    fn parse_from<Input: Read>(version: i32, bytes: &mut Input) -> Result<Stroke, ParseError> {
         let pen = Pen::try_from(parse_u32(bytes)?)?;
         let color = Color::try_from(parse_u32(bytes)?)?;
         discard_bytes(bytes, 4)?;
@@ -181,7 +283,20 @@ impl ParseFrom for Stroke {
     }
 }
 
-#[derive(Debug)]
+impl WriteTo for Stroke {
+    fn write_to<Output: Write>(&self, version: i32, output: &mut Output) -> std::io::Result<()> {
+        output.write_all(&self.pen.code(version).to_le_bytes())?;
+        output.write_all(&self.color.code().to_le_bytes())?;
+        output.write_all(&[0; 4])?;
+        output.write_all(&self.width.to_le_bytes())?;
+        if version >= 5 {
+            output.write_all(&[0; 4])?;
+        }
+        write_multiple(version, &self.segments, output)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Segment {
     // According to https://plasma.ninja/blog/devices/remarkable/binary/format/2017/12/26/reMarkable-lines-file-format.html
     pub x: f32,
@@ -193,10 +308,7 @@ pub struct Segment {
 }
 
 impl ParseFrom for Segment {
-    fn parse_from(
-        _version: i32,
-        bytes: &mut Bytes<BufReader<File>>,
-    ) -> Result<Segment, ParseError> {
+    fn parse_from<Input: Read>(_version: i32, bytes: &mut Input) -> Result<Segment, ParseError> {
         let x = parse_f32(bytes)?;
         let y = parse_f32(bytes)?;
         let speed = parse_f32(bytes)?;
@@ -217,9 +329,26 @@ impl ParseFrom for Segment {
     }
 }
 
-fn parse_multiple<T: ParseFrom>(
+impl WriteTo for Segment {
+    fn write_to<Output: Write>(&self, _version: i32, output: &mut Output) -> std::io::Result<()> {
+        for value in &[
+            self.x,
+            self.y,
+            self.speed,
+            self.direction,
+            self.width,
+            self.pressure,
+        ] {
+            output.write_all(&value.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_multiple<T: ParseFrom, Input: Read>(
     version: i32,
-    bytes: &mut Bytes<BufReader<File>>,
+    bytes: &mut Input,
 ) -> Result<Vec<T>, ParseError> {
     let count = parse_u32(bytes)?;
     let mut items: Vec<T> = vec![];
@@ -231,13 +360,35 @@ fn parse_multiple<T: ParseFrom>(
     Ok(items)
 }
 
+fn write_multiple<T: WriteTo, Output: Write>(
+    version: i32,
+    items: &[T],
+    output: &mut Output,
+) -> std::io::Result<()> {
+    output.write_all(&(items.len() as u32).to_le_bytes())?;
+    for item in items {
+        item.write_to(version, output)?;
+    }
+
+    Ok(())
+}
+
 impl LineFile {
+    #[deprecated(note = "read the file, or use a `Source`, and parse it with `from_bytes`")]
     pub fn parse(filename: &str) -> Result<LineFile, ParseError> {
-        let file = File::open(filename)?;
+        Self::from_reader(File::open(filename)?)
+    }
+
+    pub fn from_bytes(mut data: &[u8]) -> Result<LineFile, ParseError> {
+        Self::parse_input(&mut data)
+    }
 
-        let reader = BufReader::new(file);
-        let bytes = &mut reader.bytes();
+    /// Parse from `reader`, which is buffered, so it may be a file or a socket.
+    pub fn from_reader<R: Read>(reader: R) -> Result<LineFile, ParseError> {
+        Self::parse_input(&mut BufReader::new(reader))
+    }
 
+    fn parse_input<Input: Read>(bytes: &mut Input) -> Result<LineFile, ParseError> {
         parse_header(bytes)?;
 
         let version = parse_version(bytes)?;
@@ -248,92 +399,89 @@ impl LineFile {
 
         Ok(LineFile { version, layers })
     }
+
+    /// Write the file in the binary format understood by `parse` and the device.
+    pub fn write<Output: Write>(&self, output: &mut Output) -> std::io::Result<()> {
+        if !(3..=5).contains(&self.version) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Cannot write line file version {}", self.version),
+            ));
+        }
+
+        output.write_all(HEADER.as_bytes())?;
+        write!(output, "{}", self.version)?;
+        output.write_all(&[b' '; 10])?;
+
+        write_multiple(self.version, &self.layers, output)
+    }
 }
 
-fn parse_header(bytes: &mut Bytes<BufReader<File>>) -> Result<(), ParseError> {
+fn parse_header<Input: Read>(bytes: &mut Input) -> Result<(), ParseError> {
     let header = parse_string(bytes, 32)?;
-    if header == "reMarkable .lines file, version=" {
+    if header == HEADER {
         Ok(())
     } else {
         Err(ParseError::new(&format!("Invalid header: '{}'", header)))
     }
 }
 
-fn discard_bytes(bytes: &mut Bytes<BufReader<File>>, count: i32) -> Result<(), ParseError> {
-    for _ in 0..count {
-        match bytes.next() {
-            None => {
-                return Err(ParseError::new(&format!(
-                    "Unexpected end of file while discarding {} bytes",
-                    count
-                )));
-            }
-            Some(_) => {}
-        }
+/// Fill `buffer` from `bytes`, or fail with "Unexpected end of file while <what>".
+fn read_exact<Input: Read>(
+    bytes: &mut Input,
+    buffer: &mut [u8],
+    what: &str,
+) -> Result<(), ParseError> {
+    match bytes.read_exact(buffer) {
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Err(ParseError::new(&format!(
+            "Unexpected end of file while {}",
+            what
+        ))),
+        result => Ok(result?),
+    }
+}
+
+fn discard_bytes<Input: Read>(bytes: &mut Input, count: u64) -> Result<(), ParseError> {
+    let discarded = std::io::copy(&mut bytes.take(count), &mut std::io::sink())?;
+    if discarded < count {
+        return Err(ParseError::new(&format!(
+            "Unexpected end of file while discarding {} bytes",
+            count
+        )));
     }
 
     Ok(())
 }
 
-fn parse_string(bytes: &mut Bytes<BufReader<File>>, count: i32) -> Result<String, ParseError> {
-    let mut buffer: Vec<u8> = vec![];
-
-    for _ in 0..count {
-        match bytes.next() {
-            None => {
-                return Err(ParseError::new(&format!(
-                    "Unexpected end of file while parsing string of length {}",
-                    count
-                )));
-            }
-            Some(byte) => {
-                let byte = byte?;
-                buffer.push(byte);
-            }
-        }
-    }
+fn parse_string<Input: Read>(bytes: &mut Input, count: usize) -> Result<String, ParseError> {
+    let mut buffer = vec![0; count];
+    read_exact(
+        bytes,
+        &mut buffer,
+        &format!("parsing string of length {}", count),
+    )?;
 
     let string = str::from_utf8(&buffer)?;
 
     Ok(string.to_owned())
 }
 
-fn parse_u32(bytes: &mut Bytes<BufReader<File>>) -> Result<u32, ParseError> {
+fn parse_u32<Input: Read>(bytes: &mut Input) -> Result<u32, ParseError> {
     let mut buffer: [u8; 4] = [0; 4];
-
-    for i in 0..4 {
-        match bytes.next() {
-            None => {
-                return Err(ParseError::new("Unexpected end of file while parsing u32"));
-            }
-            Some(byte) => {
-                buffer[i] = byte?;
-            }
-        }
-    }
+    read_exact(bytes, &mut buffer, "parsing u32")?;
 
     // Little-endian
     Ok(u32::from_le_bytes(buffer))
 }
 
-fn parse_f32(bytes: &mut Bytes<BufReader<File>>) -> Result<f32, ParseError> {
+fn parse_f32<Input: Read>(bytes: &mut Input) -> Result<f32, ParseError> {
     let mut buffer: [u8; 4] = [0; 4];
-
-    for i in 0..4 {
-        match bytes.next() {
-            None => {
-                return Err(ParseError::new("Unexpected end of file while parsing f32"));
-            }
-            Some(byte) => {
-                buffer[i] = byte?;
-            }
-        }
-    }
+    read_exact(bytes, &mut buffer, "parsing f32")?;
 
     Ok(f32::from_le_bytes(buffer))
 }
 
-fn parse_version(bytes: &mut Bytes<BufReader<File>>) -> Result<i32, ParseError> {
+fn parse_version<Input: Read>(bytes: &mut Input) -> Result<i32, ParseError> {
     let version_string = parse_string(bytes, 1)?;
     let version: i32 = version_string.parse()?;
 
@@ -0,0 +1,187 @@
+use serde::Serialize;
+use std::error::Error;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use restorable::node::{Metadata, Node};
+use restorable::notebook::Content;
+use restorable::selector::{unique_name, unique_path};
+use restorable::source::Source;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+    Ndjson,
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "ndjson" => Ok(Format::Ndjson),
+            "csv" => Ok(Format::Csv),
+            _ => Err(format!(
+                "Invalid format '{}', expected 'text', 'json', 'ndjson' or 'csv'",
+                s
+            )),
+        }
+    }
+}
+
+/// A node as printed by `tree` and `ls`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Entry {
+    pub id: String,
+    /// Selector that can be passed to other commands
+    pub path: String,
+    pub page_count: Option<usize>,
+    pub file_type: Option<String>,
+    #[serde(flatten)]
+    pub metadata: Metadata,
+    /// Only filled in for the nested JSON format
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<Entry>,
+}
+
+impl Entry {
+    fn new(root: &Node, lineage: &[Rc<Node>], source: &dyn Source) -> Option<Entry> {
+        let node = lineage.last()?;
+        let metadata = node.metadata.clone()?;
+        let (page_count, file_type) = if node.is_notebook() {
+            match Content::load(source, &node.id) {
+                Ok(content) => (Some(content.page_ids().len()), Some(content.file_type)),
+                Err(_) => (None, None),
+            }
+        } else {
+            (None, None)
+        };
+
+        Some(Entry {
+            id: node.id.clone(),
+            path: unique_path(root, lineage),
+            page_count,
+            file_type,
+            metadata,
+            children: vec![],
+        })
+    }
+}
+
+const CSV_HEADER: &str =
+    "id,path,visibleName,type,parent,lastModified,pinned,deleted,pageCount,fileType";
+
+/// Print the children of the last node in `lineage` (or of `root`, if empty).
+pub fn print_children(
+    root: &Node,
+    lineage: &[Rc<Node>],
+    recursive: bool,
+    format: Format,
+    source: &dyn Source,
+) -> Result<(), Box<dyn Error>> {
+    let entries = || collect(root, &mut lineage.to_vec(), recursive, source);
+
+    match format {
+        Format::Text => {
+            let parent: &Node = match lineage.last() {
+                Some(parent) => parent,
+                None => root,
+            };
+            for child in parent.children.borrow().iter() {
+                let name = unique_name(parent, child);
+                if child.is_notebook() {
+                    println!("{}", name);
+                } else {
+                    println!("{}/", name);
+                }
+            }
+        }
+        Format::Json => {
+            println!("{}", serde_json::to_string_pretty(&entries())?);
+        }
+        Format::Ndjson => {
+            for entry in flatten(entries()).iter() {
+                println!("{}", serde_json::to_string(entry)?);
+            }
+        }
+        Format::Csv => {
+            println!("{}", CSV_HEADER);
+            for entry in flatten(entries()).iter() {
+                let metadata = &entry.metadata;
+                let fields = [
+                    entry.id.clone(),
+                    entry.path.clone(),
+                    metadata.visible_name.clone(),
+                    serde_json::to_value(&metadata.r#type)?
+                        .as_str()
+                        .unwrap_or("")
+                        .to_owned(),
+                    metadata.parent.clone(),
+                    metadata.last_modified.clone(),
+                    metadata.pinned.to_string(),
+                    metadata.deleted.to_string(),
+                    entry
+                        .page_count
+                        .map(|count| count.to_string())
+                        .unwrap_or_default(),
+                    entry.file_type.clone().unwrap_or_default(),
+                ];
+                let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+                println!("{}", fields.join(","));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn collect(
+    root: &Node,
+    lineage: &mut Vec<Rc<Node>>,
+    recursive: bool,
+    source: &dyn Source,
+) -> Vec<Entry> {
+    let children: Vec<Rc<Node>> = match lineage.last() {
+        Some(parent) => parent.children.borrow().clone(),
+        None => root.children.borrow().clone(),
+    };
+
+    let mut entries = vec![];
+    for child in children {
+        lineage.push(child);
+        if let Some(mut entry) = Entry::new(root, lineage, source) {
+            if recursive {
+                entry.children = collect(root, lineage, recursive, source);
+            }
+            entries.push(entry);
+        }
+        lineage.pop();
+    }
+
+    entries
+}
+
+/// Depth-first list of all entries, parents before their children.
+fn flatten(entries: Vec<Entry>) -> Vec<Entry> {
+    let mut result = vec![];
+    for mut entry in entries {
+        let children = std::mem::take(&mut entry.children);
+        result.push(entry);
+        result.extend(flatten(children));
+    }
+
+    result
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
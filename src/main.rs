@@ -1,38 +1,452 @@
-mod json;
-mod linefile;
-mod node;
-mod notebook;
-mod page;
-mod render;
-
-use crate::node::{parse_nodes, Node};
-use crate::notebook::Notebook;
-use crate::render::render_notebook;
+mod listing;
+#[cfg(feature = "serve")]
+mod serve;
+#[cfg(feature = "watch")]
+mod watch;
+
+use crate::listing::{print_children, Format};
+#[cfg(feature = "archive")]
+use restorable::archive::{is_archive, open_archive, UnsupportedArchive};
+use restorable::create::{create_notebook, NotebookSpec, PageSpec, BLANK_TEMPLATE};
+use restorable::export::{export_combined, export_notebook, ImageFormat, Part};
+use restorable::import::{import_document, parent_id};
+use restorable::layout::{Layout, MarginTooLarge, Paper};
+use restorable::linefile::{Color, Layer, LineFile, Pen, Stroke};
+use restorable::node::{parse_graph, parse_nodes, Node, ParseOptions};
+use restorable::notebook::{Content, Notebook};
+use restorable::organize::{self, Change};
+use restorable::output::{NamePolicy, PathMapper};
+use restorable::pages::PageSelection;
+use restorable::search::{Date, Kind, Pattern, Query};
+use restorable::selector::{lineage, select, select_one, unique_names, unique_path};
+#[cfg(feature = "sftp")]
+use restorable::sftp::{HostKeyError, KnownHosts, Sftp};
+use restorable::source::{Directory, Source};
+use restorable::style::{Crop, RenderOptions};
+use restorable::text::{document_text, snippet};
+use restorable::thumbnail::thumbnails;
+#[cfg(feature = "vector")]
+use restorable::vector::{vector_pages, StrokeStyle};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
+use std::ffi::OsStr;
 use std::fs::File;
 use std::path::Path;
 use std::path::PathBuf;
+use std::rc::Rc;
+use structopt::clap::AppSettings;
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
 enum Command {
+    /// Set the xochitl directory, on this computer or, with --host, on the device
     SetDir {
+        /// Directory, or backup archive ending in .zip, .tar, .tar.gz or .tgz
         path: String,
+        /// Read over SSH from this host, like "10.11.99.1" or "remarkable:22"
+        #[structopt(long)]
+        host: Option<String>,
+        #[structopt(long, default_value = "root")]
+        user: String,
+        /// Private key for SSH; without it, the SSH agent is asked
+        #[structopt(long)]
+        key: Option<PathBuf>,
+        /// Log in now and add the host's key to ~/.ssh/known_hosts if it is not there yet
+        #[structopt(long, requires = "host")]
+        accept_host_key: bool,
+    },
+    /// Print the strokes of all pages of a notebook
+    Dump {
+        /// Path like "Work/Notes" or document ID
+        notebook: String,
+        /// "json" or "cbor"
+        #[structopt(long, default_value = "json")]
+        format: DumpFormat,
+        #[structopt(flatten)]
+        filter: Filter,
+    },
+    /// Convert a page between the .rm and JSON formats, as given by the file extensions
+    Convert {
+        input_path: PathBuf,
+        output_path: PathBuf,
+    },
+    Tree {
+        /// "text", "json", "ndjson" or "csv"
+        #[structopt(long, default_value = "text")]
+        format: Format,
+        #[structopt(flatten)]
+        filter: Filter,
+    },
+    /// List the contents of a folder
+    Ls {
+        /// Folder to list, the top level if omitted
+        folder: Option<String>,
+        /// "text", "json", "ndjson" or "csv"
+        #[structopt(long, default_value = "text")]
+        format: Format,
+        #[structopt(flatten)]
+        filter: Filter,
+    },
+    /// Search documents and folders by name and metadata
+    Find {
+        /// Part of the name to look for (case-insensitive)
+        pattern: Option<String>,
+        /// Interpret the pattern as a regular expression
+        #[structopt(long)]
+        regex: bool,
+        /// Only "notebook" or only "folder"
+        #[structopt(long = "type")]
+        kind: Option<Kind>,
+        /// Only pinned documents and folders
+        #[structopt(long)]
+        pinned: bool,
+        /// Modified on or after this day (YYYY-MM-DD)
+        #[structopt(long)]
+        modified_after: Option<Date>,
+        /// Modified before this day (YYYY-MM-DD)
+        #[structopt(long)]
+        modified_before: Option<Date>,
+        /// Only search inside this folder
+        #[structopt(long = "in")]
+        folder: Option<String>,
+        #[structopt(long, possible_values = &["notebook", "pdf", "epub"])]
+        file_type: Option<String>,
+        /// Print document IDs instead of paths
+        #[structopt(long)]
+        ids: bool,
+        #[structopt(flatten)]
+        filter: Filter,
+    },
+    /// Search typed text, highlights and recognized handwriting
+    Grep {
+        /// Text to look for (case-insensitive)
+        pattern: String,
+        /// Interpret the pattern as a regular expression
+        #[structopt(long)]
+        regex: bool,
+        /// Only search documents inside this folder
+        #[structopt(long = "in")]
+        folder: Option<String>,
+        #[structopt(flatten)]
+        filter: Filter,
+    },
+    /// Report orphaned documents and cycles in the folder structure
+    Check {
+        #[structopt(flatten)]
+        filter: Filter,
     },
-    Tree,
+    #[structopt(setting = AppSettings::AllowMissingPositional)]
     RenderNotebook {
-        notebook: PathBuf,
+        /// Path like "Work/Notes", document ID, or pattern like "Work/Note*" or "Work/Notes#2";
+        /// may be omitted when there is only one notebook, like in an .rmdoc file
+        notebook: Option<String>,
+        /// Output file; the extension selects the format unless --format is given
         output_path: PathBuf,
+        /// "pdf", "svg" or "png"; SVG and PNG write one file per page
+        #[structopt(long)]
+        format: Option<ImageFormat>,
+        /// Pages to export, like "1-3,7,last"
+        #[structopt(long)]
+        pages: Option<PageSelection>,
+        /// Export the page that was last opened on the device
+        #[structopt(long, conflicts_with = "pages")]
+        current_page: bool,
+        /// Write one file per page, also for PDF
+        #[structopt(long)]
+        split: bool,
+        #[structopt(flatten)]
+        render: RenderArgs,
+        #[structopt(flatten)]
+        filter: Filter,
+    },
+    /// Render all notebooks in a folder into one PDF with bookmarks
+    RenderFolder {
+        /// Path like "Work/Meeting minutes" or folder ID
+        folder: String,
+        output_path: PathBuf,
+        /// Order of notebooks and folders: "name" or "date" (oldest first)
+        #[structopt(long, default_value = "name")]
+        sort: SortOrder,
+        #[structopt(flatten)]
+        render: RenderArgs,
+        #[structopt(flatten)]
+        filter: Filter,
+    },
+    /// Add a PDF or EPUB to the xochitl directory; restart xochitl to see it on the device
+    Import {
+        file_path: PathBuf,
+        /// Folder like "Work/Handouts", the top level if omitted
+        folder: Option<String>,
+        /// Name on the device, the file name without extension if omitted
+        #[structopt(long)]
+        name: Option<String>,
+    },
+    /// Create a notebook, from strokes in a JSON file or with empty pages
+    NewNotebook {
+        /// Path like "Work/Forms/Checklist"; the folder must exist
+        path: String,
+        /// JSON file with the pages and their strokes, see `restorable::create::NotebookSpec`
+        #[structopt(long)]
+        from: Option<PathBuf>,
+        /// Number of empty pages, unless --from is given
+        #[structopt(long, default_value = "1")]
+        pages: usize,
+        /// Template of the empty pages and of pages from --vector, like "P Lines small"
+        #[structopt(long, default_value = BLANK_TEMPLATE)]
+        template: String,
+        /// SVG or PDF drawing whose paths become strokes, one page per SVG or PDF page;
+        /// may be repeated
+        #[structopt(long, number_of_values = 1)]
+        vector: Vec<PathBuf>,
+        /// Pen for the strokes from --vector, like "fineliner" or "ballpoint"
+        #[structopt(long, default_value = "fineliner")]
+        pen: Pen,
+        /// "black", "gray" or "white"
+        #[structopt(long, default_value = "black")]
+        color: Color,
+        /// Base width of the strokes from --vector: 1.875 is thin, 2 medium, 2.125 thick
+        #[structopt(long, default_value = "2")]
+        width: f32,
+    },
+    /// Create a folder
+    Mkdir {
+        /// Path like "Work/Projects"; the parent folder must exist
+        path: String,
+        #[structopt(flatten)]
+        change: ChangeArgs,
+    },
+    /// Move documents and folders into another folder
+    Mv {
+        /// Path, document ID or pattern like "Inbox/*"
+        item: String,
+        /// Folder to move to; "/" for the top level, "Trash" to move to the trash. Items in the
+        /// trash, like "Trash/Notes", are restored by moving them to another folder
+        folder: String,
+        #[structopt(flatten)]
+        change: ChangeArgs,
+    },
+    /// Rename a document or folder
+    Rename {
+        /// Path like "Work/Notes" or document ID
+        item: String,
+        name: String,
+        #[structopt(flatten)]
+        change: ChangeArgs,
+    },
+    /// Move documents and folders to the trash
+    Rm {
+        /// Path, document ID or pattern like "Inbox/*"
+        item: String,
+        /// Flag as deleted, together with everything inside, like emptying the trash
+        #[structopt(long)]
+        permanently: bool,
+        #[structopt(flatten)]
+        change: ChangeArgs,
+    },
+    /// Write page previews as `<document ID>.jpg`, copied from the device while they are up to
+    /// date and rendered otherwise
+    Thumbs {
+        /// Path, document ID or pattern like "Work/*"; folders give all documents inside
+        item: String,
+        output_directory: PathBuf,
+        /// All pages instead of the first, as `<document ID>-<page number>.jpg`, also for
+        /// documents with a single page
+        #[structopt(long)]
+        all_pages: bool,
+        /// Width in pixels of rendered previews; the device's are copied as they are, 280
+        /// pixels wide
+        #[structopt(long, default_value = "280")]
+        width: f32,
+        #[structopt(flatten)]
+        filter: Filter,
+    },
+    /// Browse the documents and download them rendered, in a web browser
+    #[cfg(feature = "serve")]
+    Serve {
+        #[structopt(long, default_value = "8080")]
+        port: u16,
+        /// Address to listen on; the default only allows this computer, "0.0.0.0" makes the
+        /// documents readable by everyone on the network
+        #[structopt(long, default_value = "127.0.0.1")]
+        address: String,
+        /// Directory for rendered documents, "restorable" in the user's cache directory if
+        /// omitted
+        #[structopt(long)]
+        cache: Option<PathBuf>,
+        /// Megabytes the cache may take; the renders used longest ago are removed first
+        #[structopt(long, default_value = "500")]
+        cache_size: u64,
+        #[structopt(flatten)]
+        render: RenderArgs,
+        #[structopt(flatten)]
+        filter: Filter,
+    },
+    /// Render all notebooks like render-all, then again whenever they change
+    #[cfg(feature = "watch")]
+    Watch {
+        output_directory: PathBuf,
+        /// How to turn document names into file names: "portable" or "unix"
+        #[structopt(long, default_value = "portable")]
+        names: NamePolicy,
+        /// "pdf", "svg" or "png"; SVG and PNG write one file per page
+        #[structopt(long, default_value = "pdf")]
+        format: ImageFormat,
+        /// Check for changes every few seconds instead of waiting for notifications, which
+        /// network file systems like SSHFS do not send
+        #[structopt(long)]
+        poll: bool,
+        #[structopt(flatten)]
+        render: RenderArgs,
+        #[structopt(flatten)]
+        filter: Filter,
     },
     RenderAll {
         output_directory: PathBuf,
+        /// How to turn document names into file names: "portable" or "unix"
+        #[structopt(long, default_value = "portable")]
+        names: NamePolicy,
+        /// "pdf", "svg" or "png"; SVG and PNG write one file per page
+        #[structopt(long, default_value = "pdf")]
+        format: ImageFormat,
+        #[structopt(flatten)]
+        render: RenderArgs,
+        #[structopt(flatten)]
+        filter: Filter,
     },
 }
 
+#[derive(StructOpt)]
+struct Filter {
+    /// Show the trash as a top-level folder named "Trash"
+    #[structopt(long)]
+    include_trash: bool,
+    /// Include documents and folders that have been deleted
+    #[structopt(long)]
+    include_deleted: bool,
+    /// Read documents from this directory or archive (.zip, .rmdoc, .tar, .tar.gz or .tgz)
+    /// instead of the configured xochitl directory
+    #[structopt(long)]
+    input: Option<PathBuf>,
+}
+
+impl Filter {
+    fn parse_options(&self) -> ParseOptions {
+        ParseOptions {
+            include_trash: self.include_trash,
+            include_deleted: self.include_deleted,
+        }
+    }
+}
+
+#[derive(StructOpt)]
+struct ChangeArgs {
+    /// Only print the changes, without writing anything
+    #[structopt(long)]
+    dry_run: bool,
+}
+
+#[derive(StructOpt)]
+struct RenderArgs {
+    /// Drop stroke points closer than this many pixels to the simplified line
+    #[structopt(long, default_value = "0")]
+    simplify: f32,
+    /// "page", "content" (each page to its strokes), "notebook" (all pages to the strokes of
+    /// the whole notebook) or "x,y,width,height" in device pixels
+    #[structopt(long, default_value = "page")]
+    crop: Crop,
+    /// Space in pixels around the strokes when cropping to content
+    #[structopt(long, default_value = "20")]
+    margin: f32,
+    /// PDF page size: "device" prints pages at their size on the device, "a4", "letter" or "a5"
+    /// scale them to fit
+    #[structopt(long, default_value = "device")]
+    paper: Paper,
+    /// Space around and between pages on paper, in millimeters
+    #[structopt(long, default_value = "10")]
+    paper_margin: f32,
+    /// Number of pages to put on each PDF page
+    #[structopt(long, default_value = "1", possible_values = &["1", "2", "4"])]
+    n_up: usize,
+    /// Width of SVG and PNG images in pixels, their size on the device if omitted
+    #[structopt(long)]
+    width: Option<f32>,
+}
+
+impl RenderArgs {
+    fn render_options(&self) -> Result<RenderOptions, MarginTooLarge> {
+        let options = RenderOptions {
+            simplify: self.simplify,
+            crop: self.crop,
+            margin: self.margin,
+            layout: Layout {
+                paper: self.paper,
+                margin: self.paper_margin,
+                pages_per_sheet: self.n_up,
+            },
+            width: self.width,
+        };
+        options.layout.validate()?;
+
+        Ok(options)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum DumpFormat {
+    Json,
+    Cbor,
+}
+
+impl std::str::FromStr for DumpFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(DumpFormat::Json),
+            "cbor" => Ok(DumpFormat::Cbor),
+            _ => Err(format!("Invalid format '{}', expected 'json' or 'cbor'", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SortOrder {
+    Name,
+    Date,
+}
+
+impl std::str::FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(SortOrder::Name),
+            "date" => Ok(SortOrder::Date),
+            _ => Err(format!("Invalid order '{}', expected 'name' or 'date'", s)),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Dump<'a> {
+    id: &'a str,
+    name: &'a str,
+    #[serde(flatten)]
+    notebook: Notebook,
+}
+
 #[derive(Serialize, Deserialize, Default)]
 struct Config {
     xochitl_dir: String,
+    /// Read `xochitl_dir` on this host over SFTP, instead of the local file system
+    #[serde(default)]
+    host: String,
+    #[serde(default)]
+    user: String,
+    /// Private key file, empty to ask the SSH agent
+    #[serde(default)]
+    key: String,
 }
 
 const APP_NAME: &str = "restorable";
@@ -42,6 +456,7 @@ fn main() {
         Ok(_) => {}
         Err(e) => {
             eprintln!("{}", e);
+            std::process::exit(1);
         }
     }
 }
@@ -51,76 +466,526 @@ fn run() -> Result<(), Box<dyn Error>> {
 
     let command = Command::from_args();
     match command {
-        Command::SetDir { path } => {
+        Command::SetDir {
+            path,
+            host,
+            user,
+            key,
+            accept_host_key,
+        } => {
             config.xochitl_dir = path;
+            config.host = host.unwrap_or_default();
+            config.user = user;
+            config.key = key.map_or_else(String::new, |key| key.to_string_lossy().into_owned());
+            if accept_host_key {
+                accept_host(&config)?;
+            }
         }
-        Command::Tree => {
-            check_configuration(&config)?;
+        Command::Dump {
+            notebook,
+            format,
+            filter,
+        } => {
+            let source = open_source(&config, &filter)?;
 
-            let root_node = parse_nodes(&config.xochitl_dir)?;
-            for child in root_node.children.borrow().iter() {
-                child.walk(&|node, ancestors| {
-                    for _ in ancestors {
-                        print!("  ");
+            let root_node = parse_nodes(source.as_ref(), &filter.parse_options())?;
+            let node = select_one(&root_node, &notebook)?;
+            let dump = Dump {
+                id: &node.id,
+                name: node.name(),
+                notebook: Notebook::load(source.as_ref(), &node.id)?,
+            };
+            let stdout = std::io::stdout();
+            match format {
+                DumpFormat::Json => serde_json::to_writer_pretty(stdout.lock(), &dump)?,
+                DumpFormat::Cbor => write_cbor(&mut stdout.lock(), &dump)?,
+            }
+        }
+        Command::Convert {
+            input_path,
+            output_path,
+        } => {
+            let is_json = |path: &Path| path.extension() == Some(OsStr::new("json"));
+            let linefile: LineFile = if is_json(&input_path) {
+                serde_json::from_reader(std::io::BufReader::new(File::open(&input_path)?))?
+            } else {
+                LineFile::from_bytes(&std::fs::read(&input_path)?)?
+            };
+            let mut output = std::io::BufWriter::new(File::create(&output_path)?);
+            if is_json(&output_path) {
+                serde_json::to_writer_pretty(&mut output, &linefile)?;
+            } else {
+                linefile.write(&mut output)?;
+            }
+        }
+        Command::Tree { format, filter } => {
+            let source = open_source(&config, &filter)?;
+
+            let root_node = parse_nodes(source.as_ref(), &filter.parse_options())?;
+            if format == Format::Text {
+                for child in root_node.children.borrow().iter() {
+                    child.walk(&mut |node, ancestors| {
+                        for _ in ancestors {
+                            print!("  ");
+                        }
+                        if node.is_deleted() {
+                            println!("- {} (deleted)", node.name());
+                        } else {
+                            println!("- {}", node.name());
+                        }
+                    });
+                }
+            } else {
+                print_children(&root_node, &[], true, format, source.as_ref())?;
+            }
+        }
+        Command::Ls {
+            folder,
+            format,
+            filter,
+        } => {
+            let source = open_source(&config, &filter)?;
+
+            let root_node = parse_nodes(source.as_ref(), &filter.parse_options())?;
+            let lineage = match folder {
+                Some(folder) => {
+                    let node = select_one(&root_node, &folder)?;
+                    if node.is_notebook() {
+                        return Err(format!("'{}' is not a folder", folder).into());
                     }
-                    println!("- {}", node.name());
-                });
+                    lineage(&root_node, &node)
+                }
+                None => vec![],
+            };
+            print_children(&root_node, &lineage, false, format, source.as_ref())?;
+        }
+        Command::Find {
+            pattern,
+            regex,
+            kind,
+            pinned,
+            modified_after,
+            modified_before,
+            folder,
+            file_type,
+            ids,
+            filter,
+        } => {
+            let source = open_source(&config, &filter)?;
+
+            let root_node = parse_nodes(source.as_ref(), &filter.parse_options())?;
+            let name = match pattern {
+                Some(pattern) => Some(Pattern::new(&pattern, regex)?),
+                None => None,
+            };
+            let folder = match folder {
+                Some(folder) => Some(select_one(&root_node, &folder)?),
+                None => None,
+            };
+            let query = Query {
+                name,
+                kind,
+                pinned,
+                modified_after,
+                modified_before,
+                folder,
+                file_type,
+            };
+            root_node.walk(&mut |node, ancestors| {
+                if query.matches(node, ancestors, source.as_ref()) {
+                    if ids {
+                        println!("{}", node.id);
+                    } else {
+                        println!("{}", unique_path(&root_node, ancestors));
+                    }
+                }
+            });
+        }
+        Command::Grep {
+            pattern,
+            regex,
+            folder,
+            filter,
+        } => {
+            let source = open_source(&config, &filter)?;
+
+            let root_node = parse_nodes(source.as_ref(), &filter.parse_options())?;
+            let pattern = Pattern::new(&pattern, regex)?;
+            let query = Query {
+                kind: Some(Kind::Notebook),
+                folder: match folder {
+                    Some(folder) => Some(select_one(&root_node, &folder)?),
+                    None => None,
+                },
+                ..Query::default()
+            };
+            root_node.walk(&mut |node, ancestors| {
+                if !query.matches(node, ancestors, source.as_ref()) {
+                    return;
+                }
+                let texts = match document_text(source.as_ref(), &node.id) {
+                    Ok(texts) => texts,
+                    Err(_) => {
+                        eprintln!("WARNING: Failed to read text of '{}'", node.name());
+                        return;
+                    }
+                };
+                let document = unique_path(&root_node, ancestors);
+                for page_text in texts.iter() {
+                    for line in page_text.text.lines() {
+                        if let Some(position) = pattern.find(line) {
+                            let location = match page_text.page {
+                                Some(page) => format!("page {}", page),
+                                None => "document".to_owned(),
+                            };
+                            println!(
+                                "{}, {} ({}): {}",
+                                document,
+                                location,
+                                page_text.source,
+                                snippet(line, position)
+                            );
+                        }
+                    }
+                }
+            });
+        }
+        Command::Check { filter } => {
+            let source = open_source(&config, &filter)?;
+
+            let (_, problems) = parse_graph(source.as_ref(), &filter.parse_options())?;
+            if problems.is_empty() {
+                println!("No problems found.");
+            } else {
+                for problem in problems.iter() {
+                    println!("{}", problem);
+                }
+                println!(
+                    "Affected nodes are listed in the '{}' folder.",
+                    Node::LOST_AND_FOUND_NAME
+                );
             }
         }
         Command::RenderNotebook {
             notebook,
             output_path,
+            format,
+            pages,
+            current_page,
+            split,
+            render: render_args,
+            filter,
         } => {
-            check_configuration(&config)?;
+            let source = open_source(&config, &filter)?;
 
-            let root_node = parse_nodes(&config.xochitl_dir)?;
-            match root_node.get_descendant_by_name(&notebook) {
-                None => {
-                    eprintln!("Cannot find document {:#?}", notebook)
+            let format = format
+                .or_else(|| ImageFormat::from_path(&output_path))
+                .unwrap_or(ImageFormat::Pdf);
+            let root_node = parse_nodes(source.as_ref(), &filter.parse_options())?;
+            let node = match notebook {
+                Some(notebook) => select_one(&root_node, &notebook)?,
+                None => only_notebook(&root_node)?,
+            };
+            let pages = if current_page {
+                let content = Content::load(source.as_ref(), &node.id)?;
+                match node.metadata.as_ref().and_then(|m| m.last_opened_page) {
+                    Some(index) if index >= 0 => match content.page_index(index as usize) {
+                        Some(index) => PageSelection::single(index),
+                        None => {
+                            return Err(format!(
+                                "The last opened page of '{}' no longer exists",
+                                node.name()
+                            )
+                            .into())
+                        }
+                    },
+                    _ => return Err(format!("'{}' has no last opened page", node.name()).into()),
+                }
+            } else {
+                pages.unwrap_or_else(PageSelection::all)
+            };
+            render(
+                source.as_ref(),
+                &node,
+                format,
+                &output_path,
+                &pages,
+                split,
+                &render_args.render_options()?,
+            )?;
+        }
+        Command::RenderFolder {
+            folder,
+            output_path,
+            sort,
+            render: render_args,
+            filter,
+        } => {
+            let source = open_source(&config, &filter)?;
+
+            let root_node = parse_nodes(source.as_ref(), &filter.parse_options())?;
+            let folder = select_one(&root_node, &folder)?;
+            let mut notebooks = vec![];
+            folder.walk(&mut |node, ancestors| {
+                if node.is_notebook() && !is_imported(source.as_ref(), node) {
+                    // Siblings with the same name get a "#n" suffix, so their bookmarks are not merged
+                    let names = unique_names(&folder, ancestors);
+                    let key: Vec<_> = ancestors
+                        .iter()
+                        .zip(names.iter())
+                        .map(|(ancestor, name)| match sort {
+                            SortOrder::Name => (0, name.clone()),
+                            SortOrder::Date => {
+                                (ancestor.last_modified().unwrap_or(0), name.clone())
+                            }
+                        })
+                        .collect();
+                    notebooks.push((key, names, node.id.clone(), node.name().to_owned()));
+                }
+            });
+            notebooks.sort_by(|a, b| a.0.cmp(&b.0));
+
+            let mut parts = vec![];
+            for (_, mut folders, id, name) in notebooks {
+                match Notebook::load(source.as_ref(), &id) {
+                    Err(_) => eprintln!("WARNING: Failed to load notebook '{}'", name),
+                    Ok(notebook) => {
+                        println!("Rendering notebook {}...", name);
+                        folders.pop();
+                        parts.push(Part {
+                            folders,
+                            title: name,
+                            notebook,
+                        });
+                    }
                 }
-                Some(node) => render(&config, &node, &output_path)?,
             }
+            export_combined(parts, &output_path, &render_args.render_options()?)?;
+        }
+        Command::Import {
+            file_path,
+            folder,
+            name,
+        } => {
+            let source = open_target(&config)?;
+
+            let root_node = parse_nodes(source.as_ref(), &ParseOptions::default())?;
+            let folder = match folder {
+                Some(folder) => select_one(&root_node, &folder)?,
+                None => Rc::new(root_node),
+            };
+            let id = import_document(
+                source.as_ref(),
+                &file_path,
+                &std::fs::read(&file_path)?,
+                parent_id(&folder)?,
+                name.as_deref(),
+            )?;
+            println!("{}", id);
+        }
+        Command::NewNotebook {
+            path,
+            from,
+            pages,
+            template,
+            vector,
+            pen,
+            color,
+            width,
+        } => {
+            let mut spec: NotebookSpec = match &from {
+                Some(from) => serde_json::from_reader(std::io::BufReader::new(File::open(from)?))?,
+                None => NotebookSpec::default(),
+            };
+            for path in &vector {
+                for strokes in read_vector(path, pen.clone(), color.clone(), width)? {
+                    spec.pages.push(PageSpec {
+                        template: template.clone(),
+                        layers: vec![Layer { strokes }],
+                    });
+                }
+            }
+            if from.is_none() && vector.is_empty() {
+                spec = NotebookSpec::blank(pages, &template);
+            }
+            let source = open_target(&config)?;
+
+            let root_node = parse_nodes(source.as_ref(), &EDIT_OPTIONS)?;
+            let (parent, name) = split_path(&path);
+            let parent = select_folder(&root_node, parent)?;
+            let id = create_notebook(source.as_ref(), name, parent_id(&parent)?, &spec)?;
+            println!("{}", id);
+        }
+        Command::Mkdir { path, change } => {
+            let source = open_target(&config)?;
+
+            let root_node = parse_nodes(source.as_ref(), &EDIT_OPTIONS)?;
+            let (parent, name) = split_path(&path);
+            let parent = select_folder(&root_node, parent)?;
+            let changes = vec![organize::make_folder(&parent, name)?];
+            change_nodes(source.as_ref(), &root_node, &changes, change.dry_run)?;
         }
-        Command::RenderAll { output_directory } => {
-            check_configuration(&config)?;
+        Command::Mv {
+            item,
+            folder,
+            change,
+        } => {
+            let source = open_target(&config)?;
+
+            let root_node = parse_nodes(source.as_ref(), &EDIT_OPTIONS)?;
+            let folder = select_folder(&root_node, &folder)?;
+            let mut changes = vec![];
+            for node in select_many(&root_node, &item)? {
+                changes.push(organize::move_node(&node, &folder)?);
+            }
+            change_nodes(source.as_ref(), &root_node, &changes, change.dry_run)?;
+        }
+        Command::Rename { item, name, change } => {
+            let source = open_target(&config)?;
+
+            let root_node = parse_nodes(source.as_ref(), &EDIT_OPTIONS)?;
+            let node = select_one(&root_node, &item)?;
+            let changes = vec![organize::rename(&node, &name)?];
+            change_nodes(source.as_ref(), &root_node, &changes, change.dry_run)?;
+        }
+        Command::Rm {
+            item,
+            permanently,
+            change,
+        } => {
+            let source = open_target(&config)?;
+
+            let root_node = parse_nodes(source.as_ref(), &EDIT_OPTIONS)?;
+            let mut changes = vec![];
+            for node in select_many(&root_node, &item)? {
+                changes.extend(organize::remove(&node, permanently)?);
+            }
+            change_nodes(source.as_ref(), &root_node, &changes, change.dry_run)?;
+        }
+        Command::Thumbs {
+            item,
+            output_directory,
+            all_pages,
+            width,
+            filter,
+        } => {
+            let source = open_source(&config, &filter)?;
+            let root_node = parse_nodes(source.as_ref(), &filter.parse_options())?;
+            let selection = if all_pages {
+                PageSelection::all()
+            } else {
+                PageSelection::single(0)
+            };
+            std::fs::create_dir_all(&output_directory)?;
+
+            let mut documents: Vec<(String, String)> = vec![];
+            for node in select_many(&root_node, &item)? {
+                node.walk(&mut |document, _| {
+                    if document.is_notebook() && !documents.iter().any(|(id, _)| *id == document.id)
+                    {
+                        documents.push((document.id.clone(), document.name().to_owned()));
+                    }
+                });
+            }
+            for (id, name) in documents {
+                let result =
+                    thumbnails(source.as_ref(), &id, &selection, width).and_then(|thumbnails| {
+                        for thumbnail in thumbnails {
+                            let path = output_directory.join(thumbnail.file_name(&id, all_pages));
+                            std::fs::write(&path, thumbnail.data)?;
+                            println!("{}", path.display());
+                        }
+
+                        Ok(())
+                    });
+                if let Err(e) = result {
+                    eprintln!("WARNING: Failed to write thumbnails of '{}': {}", name, e);
+                }
+            }
+        }
+        Command::RenderAll {
+            output_directory,
+            names,
+            format,
+            render: render_args,
+            filter,
+        } => {
+            let source = open_source(&config, &filter)?;
+            let options = render_args.render_options()?;
             match output_directory.canonicalize() {
                 Err(_) => eprintln!("Directory does not exist: {:#?}", output_directory),
                 Ok(output_directory) => {
-                    let root_node = parse_nodes(&config.xochitl_dir)?;
-                    root_node.walk(&|node, ancestors| {
-                        if node.is_notebook() {
-                            let mut full_path = output_directory.clone();
-                            for node in ancestors {
-                                full_path.push(node.name());
-                            }
-                            match full_path.extension() {
-                                Some(_) => {
-                                    // Nothing to do, already a rendered file.
-                                }
-                                None => {
-                                    full_path.set_extension("pdf");
-                                    if let Some(parent) = full_path.parent() {
-                                        match std::fs::create_dir_all(parent) {
-                                            Err(_) => eprintln!(
-                                                "WARNING: Failed to create directory {:#?}",
-                                                parent
-                                            ),
-                                            Ok(_) => match render(&config, &node, &full_path) {
-                                                Err(_) => eprintln!(
-                                                    "WARNING: Failed to render notebook '{}'",
-                                                    node.name()
-                                                ),
-                                                Ok(_) => {}
-                                            },
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    });
+                    let root_node = parse_nodes(source.as_ref(), &filter.parse_options())?;
+                    OutputDirectory::new(output_directory, names, format).render(
+                        source.as_ref(),
+                        &root_node,
+                        &options,
+                        None,
+                    );
                 }
             }
         }
+        #[cfg(feature = "serve")]
+        Command::Serve {
+            port,
+            address,
+            cache,
+            cache_size,
+            render: render_args,
+            filter,
+        } => {
+            let source = open_source(&config, &filter)?;
+            let site = serve::Site {
+                source: source.as_ref(),
+                parse_options: filter.parse_options(),
+                render_options: render_args.render_options()?,
+                cache: match cache.or_else(serve::default_cache) {
+                    Some(cache) => cache,
+                    None => return Err("Could not find a cache directory, pass --cache".into()),
+                },
+                cache_size: cache_size.saturating_mul(1_000_000),
+            };
+            serve::serve(&site, &format!("{}:{}", address, port))?;
+        }
+        #[cfg(feature = "watch")]
+        Command::Watch {
+            output_directory,
+            names,
+            format,
+            poll,
+            render: render_args,
+            filter,
+        } => {
+            let directory = match &filter.input {
+                Some(input) => input.clone(),
+                None => {
+                    check_configuration(&config)?;
+                    if !config.host.is_empty() {
+                        return Err(
+                            "Cannot watch over SSH, mount the device with SSHFS and use --poll"
+                                .into(),
+                        );
+                    }
+                    PathBuf::from(&config.xochitl_dir)
+                }
+            };
+            if !directory.is_dir() {
+                return Err(format!("Cannot watch {:?}, it is not a directory", directory).into());
+            }
+            let output_directory = output_directory
+                .canonicalize()
+                .map_err(|_| format!("Directory does not exist: {:#?}", output_directory))?;
+            let source = Directory::new(&directory);
+            let options = render_args.render_options()?;
+            let mut output_directory = OutputDirectory::new(output_directory, names, format);
+            watch::watch(&directory, poll, &mut |changed| match parse_nodes(
+                &source,
+                &filter.parse_options(),
+            ) {
+                Err(e) => eprintln!("WARNING: Failed to read the folder structure: {}", e),
+                Ok(root_node) => output_directory.render(&source, &root_node, &options, changed),
+            })?;
+        }
     }
 
     confy::store(APP_NAME, config)?;
@@ -136,23 +1001,430 @@ fn check_configuration(config: &Config) -> Result<(), ConfigMissing> {
     }
 }
 
-fn render(config: &Config, node: &Node, output_path: &Path) -> Result<(), Box<dyn Error>> {
-    if node.is_notebook() {
-        let filename = Path::join(&PathBuf::from(&config.xochitl_dir), &node.id);
-        let filename = filename.to_str().unwrap();
-        let notebook = Notebook::load(filename)?;
+fn open_source(config: &Config, filter: &Filter) -> Result<Box<dyn Source>, Box<dyn Error>> {
+    if let Some(input) = &filter.input {
+        return open_local(input);
+    }
+    check_configuration(config)?;
+
+    if config.host.is_empty() {
+        open_local(Path::new(&config.xochitl_dir))
+    } else {
+        connect(config)
+    }
+}
 
-        let mut file = File::create(output_path)?;
+/// The trash is included, so documents can be restored from it.
+const EDIT_OPTIONS: ParseOptions = ParseOptions {
+    include_trash: true,
+    include_deleted: false,
+};
 
-        println!("Rendering notebook {}...", node.name());
-        render_notebook(notebook, &mut file)?;
+/// Folder and name of a new document or folder at `path`, like "Work" and "Projects".
+fn split_path(path: &str) -> (&str, &str) {
+    let path = path.trim_end_matches('/');
+    match path.rfind('/') {
+        Some(position) => (&path[..position], &path[position + 1..]),
+        None => ("", path),
+    }
+}
+
+/// `folder` like "Work/Projects", or the top level for "" and "/".
+fn select_folder(root_node: &Node, folder: &str) -> Result<Rc<Node>, Box<dyn Error>> {
+    if folder.trim_matches('/').is_empty() {
+        return Ok(Rc::new(Node {
+            id: Node::ROOT_ID.to_owned(),
+            ..Node::default()
+        }));
+    }
+
+    Ok(select_one(root_node, folder)?)
+}
+
+fn select_many(root_node: &Node, selector: &str) -> Result<Vec<Rc<Node>>, Box<dyn Error>> {
+    let matches = select(root_node, selector);
+    if matches.is_empty() {
+        return Err(format!("No document or folder matches '{}'", selector).into());
+    }
+
+    Ok(matches.into_iter().map(|(_, node)| node).collect())
+}
+
+/// Print `changes` and, unless `dry_run`, write them.
+fn change_nodes(
+    source: &dyn Source,
+    root_node: &Node,
+    changes: &[Change],
+    dry_run: bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut paths = std::collections::HashMap::new();
+    paths.insert(String::new(), "/".to_owned());
+    root_node.walk(&mut |node, ancestors| {
+        if !ancestors.is_empty() {
+            paths.insert(node.id.clone(), unique_path(root_node, ancestors));
+        }
+    });
+    let path_of = |id: &str| paths.get(id).cloned().unwrap_or_else(|| id.to_owned());
+
+    for change in changes {
+        if change.before.is_none() {
+            let parent = path_of(&change.after.parent);
+            println!(
+                "{}: new folder '{}' in {}",
+                change.id, change.after.visible_name, parent
+            );
+            continue;
+        }
+        for (field, before, after) in change.differences() {
+            let (before, after) = match field {
+                "parent" => (path_of(&before), path_of(&after)),
+                _ => (before, after),
+            };
+            println!(
+                "{}: {} {:?} -> {:?}",
+                path_of(&change.id),
+                field,
+                before,
+                after
+            );
+        }
+    }
+
+    if dry_run {
+        println!("Dry run, nothing was changed");
+        Ok(())
     } else {
-        eprintln!("Not a notebook: {:#?}", node.name());
+        organize::apply(source, changes)
+    }
+}
+
+/// Like `open_source`, but refuses archives, which cannot be changed.
+fn open_target(config: &Config) -> Result<Box<dyn Source>, Box<dyn Error>> {
+    check_configuration(config)?;
+
+    if config.host.is_empty() {
+        let path = Path::new(&config.xochitl_dir);
+        if !path.is_dir() {
+            return Err(
+                format!("{:?} is not a directory, archives cannot be changed", path).into(),
+            );
+        }
+        Ok(Box::new(Directory::new(path)))
+    } else {
+        connect(config)
+    }
+}
+
+#[cfg(feature = "archive")]
+fn open_local(path: &Path) -> Result<Box<dyn Source>, Box<dyn Error>> {
+    if path.is_dir() {
+        Ok(Box::new(Directory::new(path)))
+    } else if is_archive(path) {
+        Ok(Box::new(open_archive(path)?))
+    } else {
+        Err(Box::new(UnsupportedArchive(path.to_owned())))
+    }
+}
+
+#[cfg(not(feature = "archive"))]
+fn open_local(path: &Path) -> Result<Box<dyn Source>, Box<dyn Error>> {
+    if path.is_file() {
+        return Err(
+            "Reading archives is not available, restorable was built without the 'archive' feature"
+                .into(),
+        );
+    }
+
+    Ok(Box::new(Directory::new(path)))
+}
+
+/// The notebook of a source that holds a single one, like an .rmdoc file.
+fn only_notebook(root_node: &Node) -> Result<Rc<Node>, Box<dyn Error>> {
+    let mut notebooks = vec![];
+    root_node.walk(&mut |node, ancestors| {
+        if let (true, Some(last)) = (node.is_notebook(), ancestors.last()) {
+            notebooks.push((last.clone(), unique_path(root_node, ancestors)));
+        }
+    });
+    match notebooks.len() {
+        0 => Err("No notebooks found".into()),
+        1 => Ok(notebooks.remove(0).0),
+        _ => {
+            let names: Vec<_> = notebooks.into_iter().map(|(_, path)| path).collect();
+            Err(format!(
+                "Name the notebook to render, one of:\n  {}",
+                names.join("\n  ")
+            )
+            .into())
+        }
     }
+}
+
+#[cfg(feature = "sftp")]
+fn connect(config: &Config) -> Result<Box<dyn Source>, Box<dyn Error>> {
+    let known_hosts = KnownHosts::user().ok_or("Could not find the home directory")?;
+    let sftp = login(config, &known_hosts).map_err(|e| -> Box<dyn Error> {
+        match e.downcast_ref::<HostKeyError>() {
+            Some(HostKeyError::Unknown { .. }) => format!(
+                "{}\nCheck the key, then run set-dir with --accept-host-key to add it",
+                e
+            )
+            .into(),
+            _ => e,
+        }
+    })?;
+
+    Ok(Box::new(sftp))
+}
+
+#[cfg(feature = "sftp")]
+fn accept_host(config: &Config) -> Result<(), Box<dyn Error>> {
+    let known_hosts = KnownHosts {
+        accept_new: true,
+        ..KnownHosts::user().ok_or("Could not find the home directory")?
+    };
+    login(config, &known_hosts)?;
 
     Ok(())
 }
 
+#[cfg(feature = "sftp")]
+fn login(config: &Config, known_hosts: &KnownHosts) -> Result<Sftp, Box<dyn Error>> {
+    let key = match config.key.as_str() {
+        "" => None,
+        key => Some(Path::new(key)),
+    };
+
+    Sftp::connect(
+        &config.host,
+        &config.user,
+        key,
+        &config.xochitl_dir,
+        known_hosts,
+    )
+}
+
+#[cfg(not(feature = "sftp"))]
+fn connect(_config: &Config) -> Result<Box<dyn Source>, Box<dyn Error>> {
+    Err("Reading over SSH is not available, restorable was built without the 'sftp' feature".into())
+}
+
+#[cfg(not(feature = "sftp"))]
+fn accept_host(config: &Config) -> Result<(), Box<dyn Error>> {
+    connect(config).map(|_| ())
+}
+
+#[cfg(feature = "vector")]
+fn read_vector(
+    path: &Path,
+    pen: Pen,
+    color: Color,
+    width: f32,
+) -> Result<Vec<Vec<Stroke>>, Box<dyn Error>> {
+    let data = std::fs::read(path)?;
+
+    vector_pages(path, &data, &StrokeStyle { pen, color, width })
+}
+
+#[cfg(not(feature = "vector"))]
+fn read_vector(
+    _path: &Path,
+    _pen: Pen,
+    _color: Color,
+    _width: f32,
+) -> Result<Vec<Vec<Stroke>>, Box<dyn Error>> {
+    Err(
+        "Reading drawings is not available, restorable was built without the 'vector' feature"
+            .into(),
+    )
+}
+
+#[cfg(feature = "cbor")]
+fn write_cbor<T: Serialize>(
+    output: &mut dyn std::io::Write,
+    value: &T,
+) -> Result<(), Box<dyn Error>> {
+    serde_cbor::to_writer(output, value)?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "cbor"))]
+fn write_cbor<T: Serialize>(
+    _output: &mut dyn std::io::Write,
+    _value: &T,
+) -> Result<(), Box<dyn Error>> {
+    Err("CBOR output is not available, restorable was built without the 'cbor' feature".into())
+}
+
+fn is_imported(source: &dyn Source, node: &Node) -> bool {
+    match Content::load(source, &node.id) {
+        Ok(content) => content.is_imported(),
+        Err(_) => false,
+    }
+}
+
+/// What `OutputDirectory::render` wrote for a notebook.
+struct Rendered {
+    /// Mapped from the notebook's name and folders.
+    path: PathBuf,
+    files: Vec<PathBuf>,
+}
+
+/// The directory `render-all` and `watch` write to, and what they wrote.
+struct OutputDirectory {
+    path: PathBuf,
+    names: NamePolicy,
+    format: ImageFormat,
+    rendered: HashMap<String, Rendered>,
+}
+
+impl OutputDirectory {
+    fn new(path: PathBuf, names: NamePolicy, format: ImageFormat) -> Self {
+        Self {
+            path,
+            names,
+            format,
+            rendered: HashMap::new(),
+        }
+    }
+
+    /// Render all notebooks below `root_node`, or with `only`, the notebooks with these IDs
+    /// and the ones in folders with these IDs.
+    ///
+    /// Notebooks whose path changed since an earlier call are rendered again, and the files
+    /// of notebooks that were moved, renamed or are gone are removed.
+    fn render(
+        &mut self,
+        source: &dyn Source,
+        root_node: &Node,
+        options: &RenderOptions,
+        only: Option<&HashSet<String>>,
+    ) {
+        // All notebooks are mapped, so names that collide are numbered the same way every time
+        let mut mapper = PathMapper::new(&self.path, self.names);
+        let mut seen = HashSet::new();
+        root_node.walk(&mut |node, ancestors| {
+            if node.is_notebook() {
+                // The last ancestor is the node itself
+                let changed = match only {
+                    None => true,
+                    Some(only) => ancestors.iter().any(|ancestor| only.contains(&ancestor.id)),
+                };
+                if is_imported(source, node) {
+                    // Nothing to do, the original file is the rendered file.
+                    if changed {
+                        println!("Skipping '{}', an imported PDF or EPUB", node.name());
+                    }
+                    return;
+                }
+                // Siblings with the same name get a "#n" suffix, so their files do not collide
+                let full_path = match mapper.map(
+                    &unique_names(root_node, ancestors),
+                    self.format.extension(),
+                ) {
+                    Err(e) => {
+                        eprintln!("WARNING: {}", e);
+                        return;
+                    }
+                    Ok(full_path) => full_path,
+                };
+                seen.insert(node.id.clone());
+                let moved = matches!(self.rendered.get(&node.id), Some(previous) if previous.path != full_path);
+                if !changed && !moved {
+                    return;
+                }
+                if let Some(parent) = full_path.parent() {
+                    match std::fs::create_dir_all(parent) {
+                        Err(_) => eprintln!("WARNING: Failed to create directory {:#?}", parent),
+                        Ok(_) => {
+                            let result = render(
+                                source,
+                                node,
+                                self.format,
+                                &full_path,
+                                &PageSelection::all(),
+                                false,
+                                options,
+                            );
+                            match result {
+                                Err(_) => eprintln!(
+                                    "WARNING: Failed to render notebook '{}'",
+                                    node.name()
+                                ),
+                                Ok(files) => {
+                                    let current = Rendered {
+                                        path: full_path,
+                                        files,
+                                    };
+                                    if let Some(previous) =
+                                        self.rendered.insert(node.id.clone(), current)
+                                    {
+                                        self.remove(&previous, &self.rendered[&node.id].files);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        });
+
+        // Deleted, or moved to the trash while it is not shown
+        let gone: Vec<String> = self
+            .rendered
+            .keys()
+            .filter(|id| !seen.contains(*id))
+            .cloned()
+            .collect();
+        for id in gone {
+            if let Some(previous) = self.rendered.remove(&id) {
+                self.remove(&previous, &[]);
+            }
+        }
+    }
+
+    /// Remove the files of `previous` that are not in `keep`, and the folders left empty.
+    fn remove(&self, previous: &Rendered, keep: &[PathBuf]) {
+        for file in previous.files.iter().filter(|file| !keep.contains(file)) {
+            match std::fs::remove_file(file) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    eprintln!("WARNING: Failed to remove {:#?}: {}", file, e)
+                }
+                _ => println!("Removed {}", file.display()),
+            }
+            let mut folder = file.parent();
+            while let Some(path) = folder {
+                // Fails for folders that are not empty
+                if path == self.path || std::fs::remove_dir(path).is_err() {
+                    break;
+                }
+                folder = path.parent();
+            }
+        }
+    }
+}
+
+fn render(
+    source: &dyn Source,
+    node: &Node,
+    format: ImageFormat,
+    output_path: &Path,
+    pages: &PageSelection,
+    split: bool,
+    options: &RenderOptions,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    if node.is_notebook() {
+        let notebook = Notebook::load_pages(source, &node.id, pages)?;
+
+        println!("Rendering notebook {}...", node.name());
+        export_notebook(notebook, format, output_path, split, options)
+    } else {
+        eprintln!("Not a notebook: {:#?}", node.name());
+        Ok(vec![])
+    }
+}
+
 #[derive(Debug)]
 struct ConfigMissing;
 
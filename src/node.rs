@@ -1,33 +1,85 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
-use std::fs::read_dir;
-use std::path::Path;
+use std::fmt;
 use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::json;
+use crate::source::{DocumentPaths, Source};
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum NodeType {
     CollectionType,
     DocumentType,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Metadata {
-    deleted: bool,
-    last_modified: String,
-    last_opened_page: Option<i32>,
-    metadatamodified: bool,
-    modified: bool,
-    parent: String,
-    pinned: bool,
-    r#type: NodeType,
-    synced: bool,
-    version: i32,
+    pub deleted: bool,
+    pub last_modified: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_opened_page: Option<i32>,
+    pub metadatamodified: bool,
+    pub modified: bool,
+    pub parent: String,
+    pub pinned: bool,
+    pub r#type: NodeType,
+    pub synced: bool,
+    pub version: i32,
     pub visible_name: String,
+    /// Keys this version does not know, kept so they survive rewriting the file.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Metadata {
+    /// Metadata of a document or folder that is created now, below folder `parent`
+    /// (`""` for the top level).
+    pub fn new(r#type: NodeType, visible_name: &str, parent: &str) -> Self {
+        Self {
+            deleted: false,
+            last_modified: now().to_string(),
+            last_opened_page: None,
+            metadatamodified: false,
+            modified: false,
+            parent: parent.to_owned(),
+            pinned: false,
+            r#type,
+            synced: false,
+            version: 1,
+            visible_name: visible_name.to_owned(),
+            extra: serde_json::Map::new(),
+        }
+    }
+
+    fn virtual_collection(name: &str) -> Self {
+        Self {
+            deleted: false,
+            last_modified: String::new(),
+            last_opened_page: None,
+            metadatamodified: false,
+            modified: false,
+            parent: String::new(),
+            pinned: false,
+            r#type: NodeType::CollectionType,
+            synced: false,
+            version: 0,
+            visible_name: name.to_owned(),
+            extra: serde_json::Map::new(),
+        }
+    }
+}
+
+/// Controls which nodes end up in the tree returned by `parse_nodes`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ParseOptions {
+    /// Show the trash as a virtual top-level folder.
+    pub include_trash: bool,
+    /// Keep nodes that are flagged as deleted.
+    pub include_deleted: bool,
 }
 
 #[derive(Default)]
@@ -39,6 +91,10 @@ pub struct Node {
 
 impl Node {
     pub const ROOT_ID: &'static str = "root";
+    pub const TRASH_ID: &'static str = "trash";
+    pub const TRASH_NAME: &'static str = "Trash";
+    pub const LOST_AND_FOUND_ID: &'static str = "lost+found";
+    pub const LOST_AND_FOUND_NAME: &'static str = "Lost & Found";
 
     pub fn name(&self) -> &str {
         match &self.metadata {
@@ -57,27 +113,37 @@ impl Node {
         }
     }
 
-    pub fn get_descendant_by_name(&self, path: &Path) -> Option<Rc<Node>> {
-        let mut parts = path.components();
-        let name = parts.next()?;
-        let child = self.get_child_by_name(name.as_os_str().to_str()?)?;
-        let rest_of_path = parts.as_path();
-        if rest_of_path.eq(Path::new("")) {
-            Some(child)
-        } else {
-            child.get_descendant_by_name(rest_of_path)
+    pub fn is_deleted(&self) -> bool {
+        match &self.metadata {
+            Some(metadata) => metadata.deleted,
+            None => false,
+        }
+    }
+
+    pub fn is_pinned(&self) -> bool {
+        match &self.metadata {
+            Some(metadata) => metadata.pinned,
+            None => false,
+        }
+    }
+
+    /// Milliseconds since the Unix epoch.
+    pub fn last_modified(&self) -> Option<u64> {
+        match &self.metadata {
+            Some(metadata) => metadata.last_modified.parse().ok(),
+            None => None,
         }
     }
 
-    pub fn walk<F: Fn(&Self, &Vec<Rc<Node>>)>(&self, f: &F) {
+    pub fn walk<F: FnMut(&Self, &Vec<Rc<Node>>)>(&self, f: &mut F) {
         let mut ancestors = vec![];
         self.walk_with_ancestors(&mut ancestors, f);
     }
 
-    fn walk_with_ancestors<F: Fn(&Self, &Vec<Rc<Node>>)>(
+    fn walk_with_ancestors<F: FnMut(&Self, &Vec<Rc<Node>>)>(
         &self,
         ancestors: &mut Vec<Rc<Node>>,
-        f: &F,
+        f: &mut F,
     ) {
         f(self, ancestors);
         let children = self.children.borrow();
@@ -88,6 +154,15 @@ impl Node {
         }
     }
 
+    /// Order children by name, so siblings with the same name keep their "#n" suffix between runs.
+    fn sort_children(&self) {
+        let mut children = self.children.borrow_mut();
+        children.sort_by(|a, b| (a.name(), &a.id).cmp(&(b.name(), &b.id)));
+        for child in children.iter() {
+            child.sort_children();
+        }
+    }
+
     fn parent_id(&self) -> Option<&str> {
         if let Some(metadata) = &self.metadata {
             if !metadata.parent.is_empty() {
@@ -97,62 +172,108 @@ impl Node {
 
         None
     }
+}
 
-    fn get_child_by_name(&self, name: &str) -> Option<Rc<Node>> {
-        for child in self.children.borrow().iter() {
-            if child.name() == name {
-                return Some(child.clone());
-            }
-        }
+/// Inconsistency found while assembling the node graph.
+#[derive(Debug)]
+pub enum GraphProblem {
+    /// The node's parent has no metadata file.
+    Orphan {
+        id: String,
+        name: String,
+        parent_id: String,
+    },
+    /// The nodes' parents form a loop that never reaches the root.
+    Cycle { ids: Vec<String> },
+}
 
-        None
+impl fmt::Display for GraphProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GraphProblem::Orphan {
+                id,
+                name,
+                parent_id,
+            } => write!(
+                f,
+                "Orphaned node '{}' ({}): parent {} does not exist",
+                name, id, parent_id
+            ),
+            GraphProblem::Cycle { ids } => write!(f, "Cycle in parents: {}", ids.join(" -> ")),
+        }
     }
 }
 
-pub fn parse_nodes(path: &str) -> Result<Node, Box<dyn Error>> {
-    let directory = read_dir(path)?;
+pub fn parse_nodes(source: &dyn Source, options: &ParseOptions) -> Result<Node, Box<dyn Error>> {
+    let (root, _) = parse_graph(source, options)?;
 
+    Ok(root)
+}
+
+/// Like `parse_nodes`, but also reports the problems found in the graph.
+///
+/// Orphans and nodes caught in a cycle are moved to a virtual "Lost & Found" folder.
+pub fn parse_graph(
+    source: &dyn Source,
+    options: &ParseOptions,
+) -> Result<(Node, Vec<GraphProblem>), Box<dyn Error>> {
     let mut graph = GraphBuilder::new();
 
-    for entry in directory {
-        let entry = entry?;
-        let path = entry.path();
-        if let Some(extension) = path.extension() {
-            if extension == "metadata" {
-                if let Some(node_id) = path.file_stem() {
-                    let metadata: Metadata = json::parse(&path)?;
-                    if let Some(id) = node_id.to_str() {
-                        graph.add(Rc::new(Node {
-                            id: id.to_owned(),
-                            metadata: Some(metadata),
-                            children: RefCell::new(vec![]),
-                        }));
-                    }
-                }
+    for name in source.list("")? {
+        if let Some(id) = name.strip_suffix(".metadata") {
+            let metadata: Metadata = json::parse(source, &DocumentPaths::new(id).metadata())?;
+            if metadata.deleted && !options.include_deleted {
+                graph.skip(id);
+                continue;
             }
+            graph.add(Rc::new(Node {
+                id: id.to_owned(),
+                metadata: Some(metadata),
+                children: RefCell::new(vec![]),
+            }));
         }
     }
 
+    if options.include_trash {
+        // Items in the trash have "trash" as parent, which is not backed by a metadata file.
+        graph.add(Rc::new(Node {
+            id: Node::TRASH_ID.to_owned(),
+            metadata: Some(Metadata::virtual_collection(Node::TRASH_NAME)),
+            children: RefCell::new(vec![]),
+        }));
+    }
+
+    let problems = graph.validate();
     let root_nodes = graph.get_root_nodes();
 
-    Ok(Node {
+    let root = Node {
         id: Node::ROOT_ID.to_owned(),
         metadata: None,
         children: RefCell::new(root_nodes),
-    })
+    };
+    root.sort_children();
+
+    Ok((root, problems))
 }
 
 pub struct GraphBuilder {
     map: HashMap<String, Rc<Node>>,
+    skipped: HashSet<String>,
 }
 
 impl GraphBuilder {
     fn new() -> Self {
         Self {
             map: HashMap::new(),
+            skipped: HashSet::new(),
         }
     }
 
+    /// Remember a node that was left out on purpose, so its children are not reported as orphans.
+    fn skip(&mut self, id: &str) {
+        self.skipped.insert(id.to_owned());
+    }
+
     fn add(&mut self, node: Rc<Node>) {
         if let Some(placeholder) = self.map.remove(&node.id) {
             // There was a node holding children. Transfer the children.
@@ -179,11 +300,190 @@ impl GraphBuilder {
         }
     }
 
+    /// Move orphans and cycles into the "Lost & Found" folder and report them.
+    fn validate(&mut self) -> Vec<GraphProblem> {
+        let mut problems = vec![];
+        let mut lost = vec![];
+
+        // Children of placeholders whose metadata never showed up
+        let mut placeholder_ids: Vec<String> = self
+            .map
+            .values()
+            .filter(|node| {
+                node.metadata.is_none()
+                    && node.id != Node::TRASH_ID
+                    && !self.skipped.contains(&node.id)
+            })
+            .map(|node| node.id.clone())
+            .collect();
+        placeholder_ids.sort();
+        for parent_id in placeholder_ids {
+            let placeholder = self.map.remove(&parent_id).unwrap();
+            for child in placeholder.children.borrow_mut().drain(..) {
+                problems.push(GraphProblem::Orphan {
+                    id: child.id.clone(),
+                    name: child.name().to_owned(),
+                    parent_id: parent_id.clone(),
+                });
+                lost.push(child);
+            }
+        }
+
+        // Everything that is still unreachable hangs off a cycle
+        let mut reachable = HashSet::new();
+        for node in self.map.values() {
+            if node.metadata.is_none() || node.parent_id().is_none() {
+                mark_reachable(node, &mut reachable);
+            }
+        }
+        for node in lost.iter() {
+            mark_reachable(node, &mut reachable);
+        }
+        let mut ids: Vec<String> = self.map.keys().cloned().collect();
+        ids.sort();
+        for id in ids {
+            if reachable.contains(&id) {
+                continue;
+            }
+            let cycle = self.find_cycle(&id);
+            if let Some(first) = cycle.first() {
+                // Cut the cycle open at its first node
+                let node = self.map[first].clone();
+                if let Some(parent_id) = node.parent_id() {
+                    if let Some(parent) = self.map.get(parent_id) {
+                        parent
+                            .children
+                            .borrow_mut()
+                            .retain(|child| !Rc::ptr_eq(child, &node));
+                    }
+                }
+                mark_reachable(&node, &mut reachable);
+                lost.push(node);
+                problems.push(GraphProblem::Cycle { ids: cycle });
+            }
+        }
+
+        if !lost.is_empty() {
+            self.map.insert(
+                Node::LOST_AND_FOUND_ID.to_owned(),
+                Rc::new(Node {
+                    id: Node::LOST_AND_FOUND_ID.to_owned(),
+                    metadata: Some(Metadata::virtual_collection(Node::LOST_AND_FOUND_NAME)),
+                    children: RefCell::new(lost),
+                }),
+            );
+        }
+
+        problems
+    }
+
+    /// Follow the parents of `id` until a node repeats, and return the repeating part.
+    fn find_cycle(&self, id: &str) -> Vec<String> {
+        let mut path: Vec<String> = vec![];
+        let mut current = self.map.get(id);
+        while let Some(node) = current {
+            if let Some(position) = path.iter().position(|seen| seen == &node.id) {
+                return path.split_off(position);
+            }
+            path.push(node.id.clone());
+            current = node
+                .parent_id()
+                .and_then(|parent_id| self.map.get(parent_id));
+        }
+
+        vec![]
+    }
+
     fn get_root_nodes(&self) -> Vec<Rc<Node>> {
         self.map
             .values()
-            .filter(|node| node.parent_id().is_none())
+            // Placeholders without metadata (e.g. the trash) are not shown
+            .filter(|node| node.metadata.is_some() && node.parent_id().is_none())
             .map(|node| node.clone())
             .collect()
     }
 }
+
+fn mark_reachable(node: &Node, reachable: &mut HashSet<String>) {
+    if reachable.insert(node.id.clone()) {
+        for child in node.children.borrow().iter() {
+            mark_reachable(child, reachable);
+        }
+    }
+}
+
+/// Milliseconds since the Unix epoch, as in `Metadata::last_modified`.
+pub(crate) fn now() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::Memory;
+
+    fn add(source: &mut Memory, id: &str, r#type: NodeType, name: &str, parent: &str) {
+        let metadata = Metadata::new(r#type, name, parent);
+        source.insert(
+            &DocumentPaths::new(id).metadata(),
+            serde_json::to_vec(&metadata).unwrap(),
+        );
+    }
+
+    fn names(node: &Node) -> Vec<String> {
+        node.children
+            .borrow()
+            .iter()
+            .map(|child| child.name().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn nodes_hang_below_their_parents() {
+        let mut source = Memory::new();
+        add(&mut source, "work", NodeType::CollectionType, "Work", "");
+        add(
+            &mut source,
+            "notes",
+            NodeType::DocumentType,
+            "Notes",
+            "work",
+        );
+        add(&mut source, "old", NodeType::DocumentType, "Old", "trash");
+        add(&mut source, "quick", NodeType::DocumentType, "Quick", "");
+        source.insert("unrelated.txt", "");
+
+        let root = parse_nodes(&source, &ParseOptions::default()).unwrap();
+        assert_eq!(names(&root), vec!["Quick", "Work"]);
+        let work = root.children.borrow()[1].clone();
+        assert!(!work.is_notebook());
+        assert_eq!(names(&work), vec!["Notes"]);
+
+        let options = ParseOptions {
+            include_trash: true,
+            ..ParseOptions::default()
+        };
+        let root = parse_nodes(&source, &options).unwrap();
+        assert_eq!(names(&root), vec!["Quick", "Trash", "Work"]);
+        assert_eq!(names(&root.children.borrow()[1]), vec!["Old"]);
+    }
+
+    #[test]
+    fn orphans_are_lost_and_found() {
+        let mut source = Memory::new();
+        add(
+            &mut source,
+            "notes",
+            NodeType::DocumentType,
+            "Notes",
+            "gone",
+        );
+
+        let (root, problems) = parse_graph(&source, &ParseOptions::default()).unwrap();
+        assert_eq!(names(&root), vec![Node::LOST_AND_FOUND_NAME]);
+        assert_eq!(names(&root.children.borrow()[0]), vec!["Notes"]);
+        assert_eq!(problems.len(), 1);
+    }
+}
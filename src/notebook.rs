@@ -1,28 +1,107 @@
-use serde::de::DeserializeOwned;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::error::Error;
-use std::path::Path;
 
 use crate::json;
 use crate::page::Page;
-#[derive(Debug, Deserialize)]
-struct Content {
-    pages: Vec<String>,
+use crate::pages::PageSelection;
+use crate::source::{DocumentPaths, Source};
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Content {
+    #[serde(default)]
+    pub file_type: String,
+    #[serde(default)]
+    pub pages: Vec<String>,
+    /// Replaces `pages` in newer software versions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub c_pages: Option<CPages>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CPages {
+    pub pages: Vec<CPage>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CPage {
+    pub id: String,
+    pub deleted: Option<serde_json::Value>,
+}
+
+impl Content {
+    /// Load the content file of document `id`.
+    pub fn load(source: &dyn Source, id: &str) -> Result<Content, Box<dyn Error>> {
+        json::parse(source, &DocumentPaths::new(id).content())
+    }
+
+    /// IDs of the pages, in order.
+    pub fn page_ids(&self) -> Vec<String> {
+        match &self.c_pages {
+            Some(c_pages) if self.pages.is_empty() => c_pages
+                .pages
+                .iter()
+                .filter(|page| page.deleted.is_none())
+                .map(|page| page.id.clone())
+                .collect(),
+            _ => self.pages.clone(),
+        }
+    }
+
+    /// Position in `page_ids` of the page at `index` in the stored page list, which still
+    /// contains deleted pages. This is how `Metadata::last_opened_page` counts.
+    pub fn page_index(&self, index: usize) -> Option<usize> {
+        match &self.c_pages {
+            Some(c_pages) if self.pages.is_empty() => {
+                let page = c_pages.pages.get(index)?;
+                if page.deleted.is_some() {
+                    return None;
+                }
+                Some(
+                    c_pages.pages[..index]
+                        .iter()
+                        .filter(|page| page.deleted.is_none())
+                        .count(),
+                )
+            }
+            _ if index < self.pages.len() => Some(index),
+            _ => None,
+        }
+    }
+
+    /// Imported PDFs and EPUBs, as opposed to notebooks drawn on the device.
+    pub fn is_imported(&self) -> bool {
+        self.file_type == "pdf" || self.file_type == "epub"
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Notebook {
-    content: Content,
+    pub content: Content,
     pub pages: Vec<Page>,
+    /// Position of each loaded page in the whole notebook, counting from 1.
+    #[serde(skip)]
+    pub page_numbers: Vec<usize>,
 }
 
 impl Notebook {
-    pub fn load(path: &str) -> Result<Notebook, Box<dyn Error>> {
-        let content: Content = parse(path, ".content")?;
+    pub fn load(source: &dyn Source, id: &str) -> Result<Notebook, Box<dyn Error>> {
+        Self::load_pages(source, id, &PageSelection::all())
+    }
+
+    /// Load only the pages in `selection`, so large notebooks do not have to be parsed completely.
+    pub fn load_pages(
+        source: &dyn Source,
+        id: &str,
+        selection: &PageSelection,
+    ) -> Result<Notebook, Box<dyn Error>> {
+        let content = Content::load(source, id)?;
+        let page_ids = content.page_ids();
+        let indices = selection.indices(page_ids.len())?;
 
         let mut pages: Vec<Page> = vec![];
-        for page_id in &content.pages {
-            match Page::load(path, page_id) {
+        for index in &indices {
+            match Page::load(source, id, &page_ids[*index]) {
                 Err(e) => {
                     return Err(e);
                 }
@@ -32,11 +111,68 @@ impl Notebook {
             }
         }
 
-        Ok(Notebook { content, pages })
+        Ok(Notebook {
+            content,
+            pages,
+            page_numbers: indices.iter().map(|index| index + 1).collect(),
+        })
     }
 }
 
-pub fn parse<T: DeserializeOwned>(path: &str, postfix: &str) -> Result<T, Box<dyn Error>> {
-    let full_path = format!("{}{}", path, postfix);
-    json::parse(Path::new(&full_path))
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linefile::{Layer, LineFile};
+    use crate::source::Memory;
+
+    /// An empty page with `layer_count` layers, to tell pages apart.
+    fn linefile_data(layer_count: usize) -> Vec<u8> {
+        let linefile = LineFile {
+            version: 5,
+            layers: vec![Layer { strokes: vec![] }; layer_count],
+        };
+        let mut data = vec![];
+        linefile.write(&mut data).unwrap();
+
+        data
+    }
+
+    #[test]
+    fn pages_are_loaded_in_content_order() {
+        let paths = DocumentPaths::new("notes");
+        let mut source = Memory::new();
+        source.insert(&paths.content(), r#"{"pages": ["b", "a"]}"#);
+        source.insert(&paths.page("a"), linefile_data(1));
+        source.insert(&paths.page("b"), linefile_data(2));
+
+        let notebook = Notebook::load(&source, "notes").unwrap();
+        assert_eq!(notebook.page_numbers, vec![1, 2]);
+        let layer_counts: Vec<usize> = notebook
+            .pages
+            .iter()
+            .map(|page| page.linefile.layers.len())
+            .collect();
+        assert_eq!(layer_counts, vec![2, 1]);
+
+        let selection: PageSelection = "2".parse().unwrap();
+        let notebook = Notebook::load_pages(&source, "notes", &selection).unwrap();
+        assert_eq!(notebook.page_numbers, vec![2]);
+        assert_eq!(notebook.pages.len(), 1);
+    }
+
+    #[test]
+    fn page_index_skips_deleted_pages() {
+        let content: Content = serde_json::from_str(
+            r#"{"cPages": {"pages": [
+                {"id": "a"}, {"id": "b", "deleted": {"timestamp": "1:1", "value": 1}}, {"id": "c"}
+            ]}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(content.page_ids(), vec!["a", "c"]);
+        assert_eq!(content.page_index(0), Some(0));
+        assert_eq!(content.page_index(1), None);
+        assert_eq!(content.page_index(2), Some(1));
+        assert_eq!(content.page_index(3), None);
+    }
 }
@@ -0,0 +1,200 @@
+// Changing the folder structure by rewriting metadata files, the way the device does.
+
+use std::error::Error;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::import::{parent_id, NotAFolder};
+use crate::node::{now, Metadata, Node, NodeType};
+use crate::source::{DocumentPaths, Source};
+
+/// A metadata file to write.
+#[derive(Clone, Debug)]
+pub struct Change {
+    pub id: String,
+    /// `None` for a new folder.
+    pub before: Option<Metadata>,
+    pub after: Metadata,
+}
+
+impl Change {
+    fn update<F: FnOnce(&mut Metadata)>(node: &Node, f: F) -> Result<Change, NotEditable> {
+        let before = match (&node.metadata, node.id.as_str()) {
+            (_, Node::ROOT_ID) | (_, Node::TRASH_ID) | (_, Node::LOST_AND_FOUND_ID) | (None, _) => {
+                return Err(NotEditable(node.name().to_owned()))
+            }
+            (Some(metadata), _) => metadata.clone(),
+        };
+        let mut after = before.clone();
+        f(&mut after);
+        after.version += 1;
+        after.last_modified = now().to_string();
+        after.metadatamodified = true;
+
+        Ok(Change {
+            id: node.id.clone(),
+            before: Some(before),
+            after,
+        })
+    }
+
+    /// The fields that change, as `(name, before, after)`; parents are given as IDs.
+    pub fn differences(&self) -> Vec<(&'static str, String, String)> {
+        let before = match &self.before {
+            Some(before) => before,
+            None => return vec![],
+        };
+        let mut differences = vec![];
+        if before.visible_name != self.after.visible_name {
+            differences.push((
+                "visibleName",
+                before.visible_name.clone(),
+                self.after.visible_name.clone(),
+            ));
+        }
+        if before.parent != self.after.parent {
+            differences.push(("parent", before.parent.clone(), self.after.parent.clone()));
+        }
+        if before.deleted != self.after.deleted {
+            differences.push((
+                "deleted",
+                before.deleted.to_string(),
+                self.after.deleted.to_string(),
+            ));
+        }
+
+        differences
+    }
+}
+
+/// Virtual folders like the trash only exist in the tree, not as metadata files.
+#[derive(Debug)]
+pub struct NotEditable(pub String);
+
+impl Error for NotEditable {}
+
+impl fmt::Display for NotEditable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "'{}' cannot be changed", self.0)
+    }
+}
+
+/// A folder cannot be moved into itself.
+#[derive(Debug)]
+pub struct MoveIntoItself(pub String);
+
+impl Error for MoveIntoItself {}
+
+impl fmt::Display for MoveIntoItself {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Cannot move '{}' into itself", self.0)
+    }
+}
+
+/// A new folder named `name` in `parent`.
+pub fn make_folder(parent: &Node, name: &str) -> Result<Change, NotAFolder> {
+    Ok(Change {
+        id: uuid::Uuid::new_v4().to_string(),
+        before: None,
+        after: Metadata::new(NodeType::CollectionType, name, parent_id(parent)?),
+    })
+}
+
+/// Move `node` into `folder`.
+pub fn move_node(node: &Node, folder: &Node) -> Result<Change, Box<dyn Error>> {
+    let mut inside = false;
+    node.walk(&mut |descendant, _| inside |= descendant.id == folder.id);
+    if inside {
+        return Err(Box::new(MoveIntoItself(node.name().to_owned())));
+    }
+    let parent = parent_id(folder)?.to_owned();
+
+    Ok(Change::update(node, |metadata| metadata.parent = parent)?)
+}
+
+pub fn rename(node: &Node, name: &str) -> Result<Change, NotEditable> {
+    Change::update(node, |metadata| metadata.visible_name = name.to_owned())
+}
+
+/// Move `node` to the trash, or with `permanently`, flag it and everything in it as deleted,
+/// like emptying the trash does. Files are left for the device to remove.
+pub fn remove(node: &Node, permanently: bool) -> Result<Vec<Change>, NotEditable> {
+    if !permanently {
+        return Ok(vec![Change::update(node, |metadata| {
+            metadata.parent = Node::TRASH_ID.to_owned()
+        })?]);
+    }
+
+    let mut nodes: Vec<Rc<Node>> = vec![];
+    node.walk(&mut |_, ancestors| {
+        if let Some(last) = ancestors.last() {
+            nodes.push(last.clone());
+        }
+    });
+    let mut changes = vec![Change::update(node, |metadata| metadata.deleted = true)?];
+    for descendant in nodes {
+        if !descendant.is_deleted() {
+            changes.push(Change::update(&descendant, |metadata| {
+                metadata.deleted = true
+            })?);
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Write the metadata files of `changes`, and the content file of new folders.
+pub fn apply(source: &dyn Source, changes: &[Change]) -> Result<(), Box<dyn Error>> {
+    for change in changes {
+        let paths = DocumentPaths::new(&change.id);
+        if change.before.is_none() {
+            source.write(&paths.content(), b"{}")?;
+        }
+        source.write(
+            &paths.metadata(),
+            &serde_json::to_vec_pretty(&change.after)?,
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::{parse_nodes, ParseOptions};
+    use crate::source::Memory;
+
+    #[test]
+    fn unknown_keys_survive_a_rename() {
+        let mut source = Memory::new();
+        source.insert(
+            "notes.metadata",
+            r#"{
+                "deleted": false,
+                "lastModified": "1600000000000",
+                "lastOpened": "1600000000000",
+                "metadatamodified": false,
+                "modified": false,
+                "parent": "",
+                "pinned": false,
+                "synced": true,
+                "type": "DocumentType",
+                "version": 3,
+                "visibleName": "Notes",
+                "tags": [{"name": "work"}]
+            }"#,
+        );
+        let root = parse_nodes(&source, &ParseOptions::default()).unwrap();
+        let node = root.children.borrow()[0].clone();
+
+        apply(&source, &[rename(&node, "Minutes").unwrap()]).unwrap();
+
+        let written: serde_json::Value =
+            serde_json::from_slice(&source.read("notes.metadata").unwrap()).unwrap();
+        assert_eq!(written["visibleName"], "Minutes");
+        assert_eq!(written["version"], 4);
+        assert_eq!(written["lastOpened"], "1600000000000");
+        assert_eq!(written["tags"][0]["name"], "work");
+    }
+}
@@ -0,0 +1,304 @@
+// Bookmarks for PDFs, which Skia cannot write itself.
+//
+// The finished PDF is loaded with lopdf, which also reads cross-reference streams and object
+// streams, gets an outline in its document catalog, and is written out again. Items jump to
+// named destinations that were set while rendering.
+
+#[cfg(feature = "pdf")]
+use lopdf::{Dictionary, Document, Object, ObjectId, StringFormat};
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Debug, Default)]
+pub struct OutlineItem {
+    pub title: String,
+    /// Named destination to jump to.
+    pub destination: Option<String>,
+    pub children: Vec<OutlineItem>,
+    /// Created by `insert` for a path component.
+    is_folder: bool,
+}
+
+impl OutlineItem {
+    pub fn new(title: &str, destination: Option<String>) -> Self {
+        Self {
+            title: title.to_owned(),
+            destination,
+            children: vec![],
+            is_folder: false,
+        }
+    }
+
+    /// Add `item` below the items named by `path`, creating those that do not exist yet.
+    ///
+    /// Only the last item of each level is reused, so items keep the order they were inserted in.
+    /// New parents jump to the destination of their first descendant.
+    pub fn insert(items: &mut Vec<OutlineItem>, path: &[String], item: OutlineItem) {
+        match path.split_first() {
+            None => items.push(item),
+            Some((title, path)) => {
+                let is_last =
+                    matches!(items.last(), Some(last) if last.is_folder && &last.title == title);
+                if !is_last {
+                    items.push(OutlineItem {
+                        is_folder: true,
+                        ..OutlineItem::new(title, item.destination.clone())
+                    });
+                }
+                let parent = items.last_mut().unwrap();
+                if parent.destination.is_none() {
+                    parent.destination = item.destination.clone();
+                }
+                OutlineItem::insert(&mut parent.children, path, item);
+            }
+        }
+    }
+}
+
+/// The PDF cannot be given an outline.
+#[derive(Debug)]
+pub struct UnsupportedPdf(&'static str);
+
+impl Error for UnsupportedPdf {}
+
+impl fmt::Display for UnsupportedPdf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Cannot add bookmarks to the PDF: {}", self.0)
+    }
+}
+
+/// Add `items` as the document outline of the PDF in `pdf`, which is written anew.
+#[cfg(feature = "pdf")]
+pub fn add_outline(pdf: &mut Vec<u8>, items: &[OutlineItem]) -> Result<(), Box<dyn Error>> {
+    if items.is_empty() {
+        return Ok(());
+    }
+
+    let mut document = Document::load_mem(pdf)?;
+    let root = document
+        .trailer
+        .get(b"Root")
+        .and_then(Object::as_reference)
+        .map_err(|_| UnsupportedPdf("no document catalog"))?;
+
+    let outline = document.new_object_id();
+    let (first, last) = add_items(&mut document, items, outline);
+    let mut dictionary = Dictionary::new();
+    dictionary.set("Type", "Outlines");
+    dictionary.set("First", first);
+    dictionary.set("Last", last);
+    dictionary.set("Count", items.len() as i64);
+    document.objects.insert(outline, dictionary.into());
+
+    document
+        .get_object_mut(root)
+        .and_then(Object::as_dict_mut)
+        .map_err(|_| UnsupportedPdf("document catalog is not a dictionary"))?
+        .set("Outlines", outline);
+
+    // A cross-reference stream leaves its own entries in the trailer, which is written as a
+    // classic trailer
+    let mut trailer = Dictionary::new();
+    for key in &["Root", "Info", "ID"] {
+        if let Ok(value) = document.trailer.get(key.as_bytes()) {
+            trailer.set(*key, value.clone());
+        }
+    }
+    document.trailer = trailer;
+
+    pdf.clear();
+    document.save_to(pdf)?;
+
+    Ok(())
+}
+
+/// Add `items` and their descendants as objects below `parent`.
+/// Returns the IDs of the first and last item.
+#[cfg(feature = "pdf")]
+fn add_items(
+    document: &mut Document,
+    items: &[OutlineItem],
+    parent: ObjectId,
+) -> (ObjectId, ObjectId) {
+    let ids: Vec<ObjectId> = items.iter().map(|_| document.new_object_id()).collect();
+
+    for (i, item) in items.iter().enumerate() {
+        let mut dictionary = Dictionary::new();
+        dictionary.set("Title", text_string(&item.title));
+        dictionary.set("Parent", parent);
+        if i > 0 {
+            dictionary.set("Prev", ids[i - 1]);
+        }
+        if let Some(next) = ids.get(i + 1) {
+            dictionary.set("Next", *next);
+        }
+        if !item.children.is_empty() {
+            let (first, last) = add_items(document, &item.children, ids[i]);
+            dictionary.set("First", first);
+            dictionary.set("Last", last);
+            // Negative: closed until the reader clicks on it
+            dictionary.set("Count", -(item.children.len() as i64));
+        }
+        if let Some(destination) = &item.destination {
+            dictionary.set("Dest", destination.as_str());
+        }
+        document.objects.insert(ids[i], dictionary.into());
+    }
+
+    (ids[0], ids[items.len() - 1])
+}
+
+/// `text` as UTF-16 string, so any title survives without escaping.
+#[cfg(feature = "pdf")]
+fn text_string(text: &str) -> Object {
+    let mut bytes = vec![0xFE, 0xFF];
+    for unit in text.encode_utf16() {
+        bytes.extend_from_slice(&unit.to_be_bytes());
+    }
+    Object::String(bytes, StringFormat::Hexadecimal)
+}
+
+#[cfg(all(test, feature = "pdf"))]
+mod tests {
+    use super::*;
+
+    /// A one-page PDF whose cross-reference section is a stream, and whose page content
+    /// contains the words that mark a trailer.
+    fn xref_stream_pdf() -> Vec<u8> {
+        let content = b"% trailer << /Root 9 0 R >> startxref 0\n";
+        let objects = [
+            "<< /Type /Catalog /Pages 2 0 R >>".to_owned(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_owned(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 100 100] /Contents 4 0 R >>".to_owned(),
+            format!(
+                "<< /Length {} >>\nstream\n{}\nendstream",
+                content.len(),
+                String::from_utf8_lossy(content)
+            ),
+        ];
+
+        let mut pdf = b"%PDF-1.5\n".to_vec();
+        let mut offsets = vec![];
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend_from_slice(format!("{} 0 obj\n{}\nendobj\n", i + 1, object).as_bytes());
+        }
+
+        let xref_offset = pdf.len();
+        offsets.push(xref_offset);
+        let mut entries = vec![0, 0, 0, 0, 0, 0xFF];
+        for offset in &offsets {
+            entries.push(1);
+            entries.extend_from_slice(&(*offset as u32).to_be_bytes());
+            entries.push(0);
+        }
+        pdf.extend_from_slice(
+            format!(
+                "5 0 obj\n<< /Type /XRef /Size 6 /W [1 4 1] /Root 1 0 R /Length {} >>\nstream\n",
+                entries.len()
+            )
+            .as_bytes(),
+        );
+        pdf.extend_from_slice(&entries);
+        pdf.extend_from_slice(
+            format!("\nendstream\nendobj\nstartxref\n{}\n%%EOF\n", xref_offset).as_bytes(),
+        );
+
+        pdf
+    }
+
+    fn outline() -> Vec<OutlineItem> {
+        let mut items = vec![];
+        OutlineItem::insert(
+            &mut items,
+            &["Work".to_owned()],
+            OutlineItem::new("Notes ✓", Some("page1".to_owned())),
+        );
+        OutlineItem::insert(&mut items, &[], OutlineItem::new("Diary", None));
+        items
+    }
+
+    fn title(document: &Document, id: ObjectId) -> String {
+        match document.get_dictionary(id).unwrap().get(b"Title").unwrap() {
+            Object::String(bytes, _) => {
+                let units: Vec<u16> = bytes[2..]
+                    .chunks(2)
+                    .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                    .collect();
+                String::from_utf16(&units).unwrap()
+            }
+            other => panic!("Title is {:?}", other),
+        }
+    }
+
+    #[test]
+    fn outline_is_added_to_pdfs_with_cross_reference_streams() {
+        let mut pdf = xref_stream_pdf();
+        add_outline(&mut pdf, &outline()).unwrap();
+
+        let document = Document::load_mem(&pdf).unwrap();
+        assert_eq!(document.get_pages().len(), 1);
+        let outlines = document
+            .catalog()
+            .unwrap()
+            .get(b"Outlines")
+            .and_then(Object::as_reference)
+            .unwrap();
+        let outlines = document.get_dictionary(outlines).unwrap();
+        assert_eq!(outlines.get(b"Count").unwrap().as_i64().unwrap(), 2);
+
+        let first = outlines
+            .get(b"First")
+            .and_then(Object::as_reference)
+            .unwrap();
+        let last = outlines
+            .get(b"Last")
+            .and_then(Object::as_reference)
+            .unwrap();
+        assert_eq!(title(&document, first), "Work");
+        assert_eq!(title(&document, last), "Diary");
+
+        let work = document.get_dictionary(first).unwrap();
+        assert_eq!(work.get(b"Count").unwrap().as_i64().unwrap(), -1);
+        assert_eq!(
+            work.get(b"Next").and_then(Object::as_reference).unwrap(),
+            last
+        );
+        let notes = work.get(b"First").and_then(Object::as_reference).unwrap();
+        assert_eq!(title(&document, notes), "Notes ✓");
+        let notes = document.get_dictionary(notes).unwrap();
+        assert_eq!(notes.get(b"Dest").unwrap().as_name().unwrap(), b"page1");
+        assert_eq!(
+            notes.get(b"Parent").and_then(Object::as_reference).unwrap(),
+            first
+        );
+    }
+
+    #[test]
+    fn outline_survives_a_second_round() {
+        let mut pdf = xref_stream_pdf();
+        add_outline(&mut pdf, &outline()).unwrap();
+        add_outline(&mut pdf, &[OutlineItem::new("Other", None)]).unwrap();
+
+        let document = Document::load_mem(&pdf).unwrap();
+        let outlines = document
+            .catalog()
+            .unwrap()
+            .get(b"Outlines")
+            .and_then(Object::as_reference)
+            .unwrap();
+        let first = document
+            .get_dictionary(outlines)
+            .unwrap()
+            .get(b"First")
+            .and_then(Object::as_reference)
+            .unwrap();
+        assert_eq!(title(&document, first), "Other");
+    }
+
+    #[test]
+    fn garbage_is_rejected() {
+        let mut pdf = b"not a PDF".to_vec();
+        assert!(add_outline(&mut pdf, &outline()).is_err());
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+/// How document names are turned into file names.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NamePolicy {
+    /// Safe on Windows, macOS and Linux.
+    Portable,
+    /// Only replace what Unix file systems cannot store.
+    Unix,
+}
+
+impl FromStr for NamePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "portable" => Ok(NamePolicy::Portable),
+            "unix" => Ok(NamePolicy::Unix),
+            _ => Err(format!(
+                "Invalid name policy '{}', expected 'portable' or 'unix'",
+                s
+            )),
+        }
+    }
+}
+
+const REPLACEMENT: char = '_';
+const MAX_NAME_BYTES: usize = 255;
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Turn a document or folder name into a single, harmless path component.
+pub fn sanitize(name: &str, policy: NamePolicy) -> String {
+    let mut result: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\0' => REPLACEMENT,
+            '\\' | '<' | '>' | ':' | '"' | '|' | '?' | '*' if policy == NamePolicy::Portable => {
+                REPLACEMENT
+            }
+            c if c.is_control() && policy == NamePolicy::Portable => REPLACEMENT,
+            c => c,
+        })
+        .collect();
+
+    if policy == NamePolicy::Portable {
+        // Windows silently drops trailing dots and spaces
+        let trimmed_len = result.trim_end_matches(&['.', ' '][..]).len();
+        if trimmed_len < result.len() {
+            result.truncate(trimmed_len);
+            result.push(REPLACEMENT);
+        }
+        let stem = result.split('.').next().unwrap_or("");
+        if RESERVED_NAMES
+            .iter()
+            .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+        {
+            result.insert(0, REPLACEMENT);
+        }
+    }
+
+    if result.is_empty() || result == "." || result == ".." {
+        result = REPLACEMENT.to_string().repeat(result.len().max(1));
+    }
+
+    truncate(&mut result, MAX_NAME_BYTES);
+
+    result
+}
+
+fn truncate(name: &mut String, max_bytes: usize) {
+    if name.len() > max_bytes {
+        let mut end = max_bytes;
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        name.truncate(end);
+    }
+}
+
+/// `stem` followed by `suffix` and `extension`, with the stem shortened so the whole name
+/// stays within the file name limit.
+fn file_name_with(stem: &str, suffix: &str, extension: &str) -> String {
+    let mut stem = stem.to_owned();
+    truncate(
+        &mut stem,
+        MAX_NAME_BYTES - suffix.len() - extension.len() - 1,
+    );
+    format!("{}{}.{}", stem, suffix, extension)
+}
+
+/// Maps node names to files inside an output directory.
+///
+/// Every name is sanitized, and two documents never get the same file.
+pub struct PathMapper {
+    root: PathBuf,
+    policy: NamePolicy,
+    claimed: HashSet<String>,
+}
+
+impl PathMapper {
+    pub fn new(root: &Path, policy: NamePolicy) -> Self {
+        Self {
+            root: root.to_owned(),
+            policy,
+            claimed: HashSet::new(),
+        }
+    }
+
+    /// Path for the file named after the last of `names`, in folders named after the others.
+    pub fn map(&mut self, names: &[String], extension: &str) -> Result<PathBuf, UnsafePath> {
+        let mut path = self.root.clone();
+        if let Some((file_name, folders)) = names.split_last() {
+            for folder in folders {
+                path.push(sanitize(folder, self.policy));
+            }
+
+            let stem = sanitize(file_name, self.policy);
+            let mut candidate = path.join(file_name_with(&stem, "", extension));
+            let mut counter = 1;
+            while !self.claim(&candidate) {
+                counter += 1;
+                let suffix = format!(" ({})", counter);
+                candidate = path.join(file_name_with(&stem, &suffix, extension));
+            }
+            path = candidate;
+        }
+
+        let relative = path
+            .strip_prefix(&self.root)
+            .map_err(|_| UnsafePath { path: path.clone() })?;
+        if relative.as_os_str().is_empty()
+            || !relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(UnsafePath { path });
+        }
+
+        Ok(path)
+    }
+
+    fn claim(&mut self, path: &Path) -> bool {
+        let key = path.to_string_lossy();
+        let key = match self.policy {
+            // Case-insensitive file systems would let these overwrite each other
+            NamePolicy::Portable => key.to_lowercase(),
+            NamePolicy::Unix => key.into_owned(),
+        };
+
+        self.claimed.insert(key)
+    }
+}
+
+#[derive(Debug)]
+pub struct UnsafePath {
+    pub path: PathBuf,
+}
+
+impl Error for UnsafePath {}
+
+impl fmt::Display for UnsafePath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Refusing to write {:#?} outside of the output directory",
+            self.path
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbered_names_stay_within_the_limit() {
+        let mut mapper = PathMapper::new(Path::new("out"), NamePolicy::Portable);
+        let names = vec!["x".repeat(300)];
+        let first = mapper.map(&names, "pdf").unwrap();
+        let second = mapper.map(&names, "pdf").unwrap();
+
+        assert_ne!(first, second);
+        assert!(second.to_string_lossy().ends_with(" (2).pdf"));
+        for path in &[first, second] {
+            assert!(path.file_name().unwrap().len() <= MAX_NAME_BYTES);
+        }
+    }
+}
@@ -1,21 +1,24 @@
+use crate::json;
 use crate::linefile::LineFile;
-use crate::notebook::parse;
-use serde::Deserialize;
+use crate::source::{DocumentPaths, Source};
+use serde::{Deserialize, Serialize};
 use std::error::Error;
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Page {
     pub metadata: Metadata,
     pub linefile: LineFile,
 }
 
 impl Page {
-    pub fn load(path: &str, id: &str) -> Result<Page, Box<dyn Error>> {
-        let page_path = format!("{}/{}", path, id);
+    /// Load page `id` of the notebook `notebook_id`.
+    pub fn load(source: &dyn Source, notebook_id: &str, id: &str) -> Result<Page, Box<dyn Error>> {
+        let paths = DocumentPaths::new(notebook_id);
 
-        let linefile = LineFile::parse(&format!("{}.rm", page_path))?;
+        let data = source.read(&paths.page(id))?;
+        let linefile = LineFile::from_bytes(&data)?;
 
-        match parse::<Metadata>(&page_path, "-metadata.json") {
+        match json::parse::<Metadata>(source, &paths.page_metadata(id)) {
             Ok(metadata) => Ok(Page { metadata, linefile }),
             Err(_) => {
                 eprintln!("WARNING: Failed to load metadata for page {}", id);
@@ -28,12 +31,72 @@ impl Page {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Metadata {
-    layers: Vec<Layer>,
+    pub layers: Vec<Layer>,
 }
 
-#[derive(Debug, Deserialize)]
-struct Layer {
-    name: String,
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Layer {
+    pub name: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linefile::{Color, Layer as Strokes, Pen, Segment, Stroke};
+    use crate::source::Memory;
+
+    fn linefile_data(x: f32) -> Vec<u8> {
+        let point = |x, y| Segment {
+            x,
+            y,
+            speed: 0.0,
+            direction: 0.0,
+            width: 2.0,
+            pressure: 0.5,
+        };
+        let linefile = LineFile {
+            version: 5,
+            layers: vec![Strokes {
+                strokes: vec![Stroke {
+                    pen: Pen::Fineliner,
+                    color: Color::Black,
+                    width: 2.0,
+                    segments: vec![point(x, 200.0), point(x + 100.0, 300.0)],
+                }],
+            }],
+        };
+        let mut data = vec![];
+        linefile.write(&mut data).unwrap();
+
+        data
+    }
+
+    #[test]
+    fn pages_have_strokes_and_layer_names() {
+        let paths = DocumentPaths::new("notes");
+        let mut source = Memory::new();
+        source.insert(&paths.page("a"), linefile_data(100.0));
+        source.insert(
+            &paths.page_metadata("a"),
+            r#"{"layers": [{"name": "Sketch"}]}"#,
+        );
+
+        let page = Page::load(&source, "notes", "a").unwrap();
+        assert_eq!(page.metadata.layers[0].name, "Sketch");
+        let segments = &page.linefile.layers[0].strokes[0].segments;
+        assert_eq!((segments[1].x, segments[1].y), (200.0, 300.0));
+    }
+
+    #[test]
+    fn page_metadata_is_optional() {
+        let mut source = Memory::new();
+        source.insert(&DocumentPaths::new("notes").page("a"), linefile_data(0.0));
+
+        let page = Page::load(&source, "notes", "a").unwrap();
+        assert!(page.metadata.layers.is_empty());
+        assert_eq!(page.linefile.layers.len(), 1);
+        assert!(Page::load(&source, "notes", "b").is_err());
+    }
 }
@@ -0,0 +1,152 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// A page number as given by the user, counting from 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PageNumber {
+    Number(usize),
+    Last,
+}
+
+impl PageNumber {
+    fn resolve(&self, page_count: usize) -> usize {
+        match self {
+            PageNumber::Number(number) => *number,
+            PageNumber::Last => page_count,
+        }
+    }
+}
+
+impl FromStr for PageNumber {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "last" => Ok(PageNumber::Last),
+            s => match s.parse() {
+                Ok(0) | Err(_) => Err(format!(
+                    "Invalid page '{}', expected a number starting at 1 or 'last'",
+                    s
+                )),
+                Ok(number) => Ok(PageNumber::Number(number)),
+            },
+        }
+    }
+}
+
+/// Pages to export, like "1-3,7,last".
+#[derive(Clone, Debug, PartialEq)]
+pub struct PageSelection {
+    ranges: Vec<(PageNumber, PageNumber)>,
+}
+
+impl PageSelection {
+    pub fn all() -> Self {
+        Self {
+            ranges: vec![(PageNumber::Number(1), PageNumber::Last)],
+        }
+    }
+
+    /// The page at `index`, counting from 0 like `last_opened_page`.
+    pub fn single(index: usize) -> Self {
+        let page = PageNumber::Number(index + 1);
+        Self {
+            ranges: vec![(page, page)],
+        }
+    }
+
+    /// Indices of the selected pages in a document with `page_count` pages, in the order given.
+    ///
+    /// Only the whole document, "1-last", may be selected from an empty document.
+    pub fn indices(&self, page_count: usize) -> Result<Vec<usize>, PageOutOfRange> {
+        let mut indices = vec![];
+        for (first, last) in &self.ranges {
+            if page_count == 0 && (*first, *last) == (PageNumber::Number(1), PageNumber::Last) {
+                continue;
+            }
+            let (first, last) = (first.resolve(page_count), last.resolve(page_count));
+            for number in &[first, last] {
+                if *number == 0 || *number > page_count {
+                    return Err(PageOutOfRange {
+                        number: *number,
+                        page_count,
+                    });
+                }
+            }
+            if first <= last {
+                indices.extend((first..=last).map(|number| number - 1));
+            } else {
+                indices.extend((last..=first).rev().map(|number| number - 1));
+            }
+        }
+
+        Ok(indices)
+    }
+}
+
+impl FromStr for PageSelection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut ranges = vec![];
+        for part in s.split(',') {
+            let range = match part.find('-') {
+                Some(position) => (part[..position].parse()?, part[position + 1..].parse()?),
+                None => {
+                    let page = part.parse()?;
+                    (page, page)
+                }
+            };
+            ranges.push(range);
+        }
+
+        Ok(Self { ranges })
+    }
+}
+
+#[derive(Debug)]
+pub struct PageOutOfRange {
+    pub number: usize,
+    pub page_count: usize,
+}
+
+impl Error for PageOutOfRange {}
+
+impl fmt::Display for PageOutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.page_count {
+            0 => write!(
+                f,
+                "Page {} does not exist, the document is empty",
+                self.number
+            ),
+            1 => write!(
+                f,
+                "Page {} does not exist, the document has 1 page",
+                self.number
+            ),
+            page_count => write!(
+                f,
+                "Page {} does not exist, the document has {} pages",
+                self.number, page_count
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_documents_only_have_all_pages() {
+        assert_eq!(
+            PageSelection::all().indices(0).unwrap(),
+            Vec::<usize>::new()
+        );
+        assert!(PageSelection::single(0).indices(0).is_err());
+        let selection: PageSelection = "5".parse().unwrap();
+        assert!(selection.indices(0).is_err());
+    }
+}
@@ -1,19 +1,47 @@
-use crate::linefile::{Color, Layer, Pen, Stroke};
+use crate::geometry::BoundingBox;
+#[cfg(feature = "pdf")]
+use crate::layout::{Placement, Sheet};
+use crate::linefile::{Layer, Stroke};
+#[cfg(feature = "pdf")]
 use crate::notebook::Notebook;
 use crate::page::Page;
+use crate::style::{color, stroke_width, RenderOptions, Rgba};
 use skia_safe as skia;
 use std::io::Write;
 
+#[cfg(feature = "pdf")]
 pub fn render_notebook<Output: Write>(
     notebook: Notebook,
     output: &mut Output,
+    options: &RenderOptions,
+) -> Result<(), std::io::Error> {
+    let frames = options.frames(&notebook.pages);
+    render_pages(notebook.pages, &frames, &[], output, options)
+}
+
+/// Render `pages` into a PDF, each cut to the frame at the same position.
+///
+/// If `destinations` are given, every page gets the named destination at its position,
+/// so links and bookmarks can jump to it.
+#[cfg(feature = "pdf")]
+pub fn render_pages<Output: Write>(
+    pages: Vec<Page>,
+    frames: &[BoundingBox],
+    destinations: &[String],
+    output: &mut Output,
+    options: &RenderOptions,
 ) -> Result<(), std::io::Error> {
     // TODO: metadata
 
     let mut document = skia_safe::pdf::new_document(None);
 
-    for page in notebook.pages {
-        document = render_page(page, document)?;
+    let mut pages = pages.into_iter();
+    let mut destinations = destinations.iter();
+    for sheet in options.layout.sheets(frames) {
+        let count = sheet.placements.len();
+        let sheet_pages = pages.by_ref().take(count).collect();
+        let sheet_destinations: Vec<&String> = destinations.by_ref().take(count).collect();
+        document = render_sheet(sheet_pages, &sheet, &sheet_destinations, document, options)?;
     }
 
     let data = document.close();
@@ -23,40 +51,134 @@ pub fn render_notebook<Output: Write>(
     Ok(())
 }
 
-pub fn render_page(page: Page, document: skia::Document) -> Result<skia::Document, std::io::Error> {
-    let mut document = document.begin_page((1404, 1874), None);
+/// Add a PDF page with `pages` placed as given by `sheet`.
+#[cfg(feature = "pdf")]
+pub fn render_sheet(
+    pages: Vec<Page>,
+    sheet: &Sheet,
+    destinations: &[&String],
+    document: skia::Document,
+    options: &RenderOptions,
+) -> Result<skia::Document, std::io::Error> {
+    let mut document = document.begin_page((sheet.width, sheet.height), None);
+
+    let canvas = document.canvas();
+    for (i, (page, placement)) in pages.into_iter().zip(sheet.placements.iter()).enumerate() {
+        let frame = &placement.frame;
+        canvas.save();
+        canvas.translate((placement.x, placement.y));
+        if let Some(destination) = destinations.get(i) {
+            // Skia reads the name as C string
+            let name = skia::Data::new_copy(format!("{}\0", destination).as_bytes());
+            canvas.annotate_named_destination((0.0, 0.0), &name);
+        }
+        canvas.scale((placement.scale, placement.scale));
+        canvas.clip_rect(
+            skia::Rect::from_wh(frame.width(), frame.height()),
+            None,
+            None,
+        );
+        canvas.translate((-frame.min_x, -frame.min_y));
+        draw_page(page, canvas, options)?;
+        canvas.restore();
+    }
+
+    Ok(document.end_page())
+}
+
+/// Add a PDF page with the part of `page` inside `frame`, one point per device pixel.
+#[cfg(feature = "pdf")]
+#[deprecated(note = "use `render_sheet`, which also places pages on paper")]
+pub fn render_page(
+    page: Page,
+    frame: &BoundingBox,
+    document: skia::Document,
+    options: &RenderOptions,
+) -> Result<skia::Document, std::io::Error> {
+    let sheet = Sheet {
+        width: frame.width(),
+        height: frame.height(),
+        placements: vec![Placement {
+            frame: *frame,
+            x: 0.0,
+            y: 0.0,
+            scale: 1.0,
+        }],
+    };
+    render_sheet(vec![page], &sheet, &[], document, options)
+}
+
+/// Render the part of a single page inside `frame` as PNG image.
+#[cfg(feature = "raster")]
+pub fn render_page_png<Output: Write>(
+    page: Page,
+    frame: &BoundingBox,
+    output: &mut Output,
+    options: &RenderOptions,
+) -> Result<(), std::io::Error> {
+    let scale = options.scale(frame);
+    let size = (
+        (frame.width() * scale).ceil() as i32,
+        (frame.height() * scale).ceil() as i32,
+    );
+    let mut surface = skia::Surface::new_raster_n32_premul(size)
+        .ok_or_else(|| std::io::Error::other("Cannot create image"))?;
+    let canvas = surface.canvas();
+    canvas.clear(skia::Color::WHITE);
+    canvas.scale((scale, scale));
+    canvas.translate((-frame.min_x, -frame.min_y));
+
+    draw_page(page, canvas, options)?;
+
+    let data = surface
+        .image_snapshot()
+        .encode_to_data(skia::EncodedImageFormat::PNG)
+        .ok_or_else(|| std::io::Error::other("Cannot encode PNG"))?;
+    output.write_all(data.as_ref())?;
 
-    // TODO:
-    // canvas.set_line_cap_style(CapStyle::Round)?;
-    // canvas.set_line_join_style(JoinStyle::Round)?;
+    Ok(())
+}
 
+pub fn draw_page(
+    page: Page,
+    canvas: &mut skia::Canvas,
+    options: &RenderOptions,
+) -> Result<(), std::io::Error> {
     for layer in page.linefile.layers {
-        render_layer(layer, document.canvas())?;
+        render_layer(layer, canvas, options)?;
     }
 
-    Ok(document.end_page())
+    Ok(())
 }
 
-pub fn render_layer(layer: Layer, canvas: &mut skia::Canvas) -> Result<(), std::io::Error> {
+pub fn render_layer(
+    layer: Layer,
+    canvas: &mut skia::Canvas,
+    options: &RenderOptions,
+) -> Result<(), std::io::Error> {
     for stroke in layer.strokes {
-        render_stroke(stroke, canvas)?;
+        render_stroke(stroke, canvas, options)?;
     }
 
     Ok(())
 }
 
-pub fn render_stroke(stroke: Stroke, canvas: &mut skia::Canvas) -> Result<(), std::io::Error> {
+pub fn render_stroke(
+    stroke: Stroke,
+    canvas: &mut skia::Canvas,
+    options: &RenderOptions,
+) -> Result<(), std::io::Error> {
     // TODO: set fill color, pressure, etc.
 
     let mut paint = skia::Paint::default();
-    paint.set_color(color(&stroke));
+    paint.set_color(skia_color(color(&stroke)));
     paint.set_stroke_width(stroke_width(&stroke));
     paint.set_style(skia::PaintStyle::Stroke);
     paint.set_stroke_cap(skia::paint::Cap::Round);
     paint.set_stroke_join(skia::paint::Join::Round);
 
     let mut path = skia::Path::new();
-    for (i, segment) in stroke.segments.iter().enumerate() {
+    for (i, segment) in options.points(&stroke).segments.iter().enumerate() {
         let (x, y) = (segment.x, segment.y);
         if i == 0 {
             path.move_to((x, y));
@@ -70,32 +192,6 @@ pub fn render_stroke(stroke: Stroke, canvas: &mut skia::Canvas) -> Result<(), st
     Ok(())
 }
 
-fn color(stroke: &Stroke) -> skia::Color {
-    match stroke.pen {
-        Pen::Highlighter => skia::Color::from_argb(128, 255, 255, 0),
-        Pen::Eraser => skia::Color::WHITE,
-        _ => match stroke.color {
-            Color::Black => skia::Color::BLACK,
-            Color::Gray => skia::Color::GRAY,
-            Color::White => skia::Color::WHITE,
-        },
-    }
-}
-
-fn stroke_width(stroke: &Stroke) -> f32 {
-    // Determined by trial and error
-    let w = stroke.width;
-    pen_scale(&stroke.pen) * (w * w * w - 4.0)
-}
-
-fn pen_scale(pen: &Pen) -> f32 {
-    // Determined by trial and error
-    match pen {
-        Pen::Marker => 3.0,
-        Pen::PaintBrush => 2.5,
-        Pen::Pencil => 2.0,
-        Pen::Highlighter => 5.0,
-        Pen::Eraser => 4.0,
-        _ => 1.0,
-    }
+fn skia_color(color: Rgba) -> skia::Color {
+    skia::Color::from_argb(color.a, color.r, color.g, color.b)
 }
@@ -0,0 +1,316 @@
+// Text in version 6 .lines files, see https://github.com/ricklupton/rmscene
+//
+// A v6 file is a sequence of blocks. Typed text lives in the root text block and
+// highlighted PDF/EPUB text in glyph range blocks. Inside a block, values are tagged
+// with their index and type; strings are stored as length, ASCII flag and bytes.
+//
+// Text items are CRDT sequence items. They are concatenated in file order, which is
+// the order in which they were typed unless the text was edited in the middle.
+
+use std::str;
+
+use crate::linefile::ParseError;
+
+const HEADER: &[u8] = b"reMarkable .lines file, version=6          ";
+
+const ROOT_TEXT_BLOCK: u8 = 0x07;
+const GLYPH_RANGE_BLOCK: u8 = 0x0D;
+
+const TAG_BYTE1: u64 = 0x1;
+const TAG_BYTE4: u64 = 0x4;
+const TAG_BYTE8: u64 = 0x8;
+const TAG_LENGTH4: u64 = 0xC;
+const TAG_ID: u64 = 0xF;
+
+/// Whether `data` is the content of a v6 .rm file.
+pub fn is_v6(data: &[u8]) -> bool {
+    data.starts_with(HEADER)
+}
+
+/// Typed and highlighted text of a v6 page, one entry per block.
+pub fn extract_text(data: &[u8]) -> Result<Vec<String>, ParseError> {
+    if !is_v6(data) {
+        return Err(ParseError::new("Not a version 6 .lines file"));
+    }
+
+    let mut texts = vec![];
+    let mut reader = Reader::new(&data[HEADER.len()..]);
+    while !reader.is_empty() {
+        let length = reader.u32()? as usize;
+        reader.skip(3)?; // unknown, min version, current version
+        let block_type = reader.u8()?;
+        let block = reader.take(length)?;
+        if block_type == ROOT_TEXT_BLOCK || block_type == GLYPH_RANGE_BLOCK {
+            let mut text = String::new();
+            collect_strings(block, &mut text);
+            if !text.is_empty() {
+                texts.push(text);
+            }
+        }
+    }
+
+    Ok(texts)
+}
+
+/// Append all strings found in a block of tagged values.
+fn collect_strings(data: &[u8], text: &mut String) {
+    if let Some(string) = as_string(data) {
+        text.push_str(&string);
+    } else if let Some(subblocks) = tagged_subblocks(data) {
+        for subblock in subblocks {
+            collect_strings(subblock, text);
+        }
+    } else {
+        // Lists start with the number of items
+        let mut reader = Reader::new(data);
+        if reader.varuint().is_ok() {
+            if let Some(subblocks) = tagged_subblocks(reader.rest()) {
+                for subblock in subblocks {
+                    collect_strings(subblock, text);
+                }
+            }
+        }
+    }
+}
+
+/// Interpret `data` as a string value, optionally followed by a format code.
+fn as_string(data: &[u8]) -> Option<String> {
+    let mut reader = Reader::new(data);
+    let length = reader.varuint().ok()? as usize;
+    let is_ascii = reader.u8().ok()?;
+    if is_ascii > 1 {
+        return None;
+    }
+    let bytes = reader.take(length).ok()?;
+    let string = str::from_utf8(bytes).ok()?;
+    if !reader.is_empty() {
+        let tag = reader.varuint().ok()?;
+        if tag & 0xF != TAG_BYTE4 {
+            return None;
+        }
+        reader.skip(4).ok()?;
+        if !reader.is_empty() {
+            return None;
+        }
+    }
+
+    Some(string.to_owned())
+}
+
+/// The subblocks of `data`, if it consists of nothing but tagged values.
+fn tagged_subblocks(data: &[u8]) -> Option<Vec<&[u8]>> {
+    let mut reader = Reader::new(data);
+    let mut subblocks = vec![];
+    while !reader.is_empty() {
+        match reader.varuint().ok()? & 0xF {
+            TAG_BYTE1 => reader.skip(1).ok()?,
+            TAG_BYTE4 => reader.skip(4).ok()?,
+            TAG_BYTE8 => reader.skip(8).ok()?,
+            TAG_ID => {
+                reader.skip(1).ok()?;
+                reader.varuint().ok()?;
+            }
+            TAG_LENGTH4 => {
+                let length = reader.u32().ok()? as usize;
+                subblocks.push(reader.take(length).ok()?);
+            }
+            _ => return None,
+        }
+    }
+
+    Some(subblocks)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.data[self.position..]
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], ParseError> {
+        if self.data.len() - self.position < count {
+            return Err(ParseError::new(&format!(
+                "Unexpected end of block while reading {} bytes",
+                count
+            )));
+        }
+        let bytes = &self.data[self.position..self.position + count];
+        self.position += count;
+
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, count: usize) -> Result<(), ParseError> {
+        self.take(count).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8, ParseError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, ParseError> {
+        let mut buffer: [u8; 4] = [0; 4];
+        buffer.copy_from_slice(self.take(4)?);
+
+        // Little-endian
+        Ok(u32::from_le_bytes(buffer))
+    }
+
+    fn varuint(&mut self) -> Result<u64, ParseError> {
+        let mut result: u64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift >= 64 {
+                return Err(ParseError::new("Variable length integer too long"));
+            }
+            result |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+            shift += 7;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varuint(mut value: u64) -> Vec<u8> {
+        let mut bytes = vec![];
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(byte);
+                return bytes;
+            }
+            bytes.push(byte | 0x80);
+        }
+    }
+
+    fn tag(index: u64, tag_type: u64) -> Vec<u8> {
+        varuint(index << 4 | tag_type)
+    }
+
+    fn subblock(index: u64, content: &[u8]) -> Vec<u8> {
+        let mut bytes = tag(index, TAG_LENGTH4);
+        bytes.extend(&(content.len() as u32).to_le_bytes());
+        bytes.extend(content);
+        bytes
+    }
+
+    fn id(index: u64, value: u64) -> Vec<u8> {
+        let mut bytes = tag(index, TAG_ID);
+        bytes.push(1);
+        bytes.extend(varuint(value));
+        bytes
+    }
+
+    fn string(text: &str) -> Vec<u8> {
+        let mut bytes = varuint(text.len() as u64);
+        bytes.push(1);
+        bytes.extend(text.as_bytes());
+        bytes
+    }
+
+    /// A text item: its ID, the IDs left and right of it, the deleted length and the text.
+    fn item(value: u64, text: &str) -> Vec<u8> {
+        let mut bytes = id(1, value);
+        bytes.extend(id(2, 0));
+        bytes.extend(id(3, 0));
+        bytes.extend(tag(4, TAG_BYTE4));
+        bytes.extend(&0u32.to_le_bytes());
+        bytes.extend(subblock(5, &string(text)));
+        subblock(0, &bytes)
+    }
+
+    fn block(block_type: u8, content: &[u8]) -> Vec<u8> {
+        let mut bytes = (content.len() as u32).to_le_bytes().to_vec();
+        bytes.extend(&[0, 1, 1, block_type]);
+        bytes.extend(content);
+        bytes
+    }
+
+    fn root_text(items: &[(u64, &str)]) -> Vec<u8> {
+        let mut sequence = varuint(items.len() as u64);
+        for (value, text) in items {
+            sequence.extend(item(*value, text));
+        }
+        let mut content = subblock(1, &subblock(1, &sequence));
+        // The position of the text box
+        content.extend(tag(3, TAG_BYTE8));
+        content.extend(&(-468.0f64).to_le_bytes());
+        content.extend(tag(4, TAG_BYTE8));
+        content.extend(&234.0f64.to_le_bytes());
+        block(ROOT_TEXT_BLOCK, &content)
+    }
+
+    fn page(blocks: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = HEADER.to_vec();
+        for block in blocks {
+            data.extend(block);
+        }
+        data
+    }
+
+    #[test]
+    fn text_items_are_joined_per_block() {
+        let data = page(&[
+            block(0x05, &subblock(1, &string("Not text"))),
+            root_text(&[(20, "Budget for "), (200, "next quarter")]),
+        ]);
+        assert!(is_v6(&data));
+        assert_eq!(
+            extract_text(&data).unwrap(),
+            vec!["Budget for next quarter"]
+        );
+    }
+
+    #[test]
+    fn highlights_are_text() {
+        let mut content = tag(1, TAG_BYTE4);
+        content.extend(&7u32.to_le_bytes());
+        content.extend(subblock(2, &string("The budget was approved")));
+        let data = page(&[block(GLYPH_RANGE_BLOCK, &content)]);
+        assert_eq!(
+            extract_text(&data).unwrap(),
+            vec!["The budget was approved"]
+        );
+    }
+
+    #[test]
+    fn pages_without_text_are_empty() {
+        assert!(extract_text(&page(&[])).unwrap().is_empty());
+        assert!(extract_text(&page(&[root_text(&[])])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn truncated_pages_are_errors() {
+        let data = page(&[root_text(&[(20, "Budget")])]);
+        for length in HEADER.len() + 1..data.len() {
+            assert!(extract_text(&data[..length]).is_err(), "{}", length);
+        }
+        assert!(extract_text(&data[..HEADER.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn long_varuints_are_errors() {
+        assert!(Reader::new(&[0xFF; 11]).varuint().is_err());
+        assert!(Reader::new(&[0x80]).varuint().is_err());
+        assert_eq!(Reader::new(&[0xAC, 0x02]).varuint().unwrap(), 300);
+    }
+}
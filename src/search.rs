@@ -0,0 +1,211 @@
+use regex::Regex;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use crate::node::Node;
+use crate::notebook::Content;
+use crate::source::Source;
+
+pub enum Pattern {
+    /// Case-insensitive substring, matched as an escaped regular expression
+    Substring(Regex),
+    Regex(Regex),
+}
+
+impl Pattern {
+    pub fn new(pattern: &str, regex: bool) -> Result<Pattern, regex::Error> {
+        if regex {
+            Ok(Pattern::Regex(Regex::new(pattern)?))
+        } else {
+            let escaped = format!("(?i){}", regex::escape(pattern));
+            Ok(Pattern::Substring(Regex::new(&escaped)?))
+        }
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        self.find(text).is_some()
+    }
+
+    /// Byte offset of the first match in `text`.
+    pub fn find(&self, text: &str) -> Option<usize> {
+        match self {
+            Pattern::Substring(regex) | Pattern::Regex(regex) => {
+                regex.find(text).map(|m| m.start())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Notebook,
+    Folder,
+}
+
+impl FromStr for Kind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "notebook" | "document" => Ok(Kind::Notebook),
+            "folder" | "collection" => Ok(Kind::Folder),
+            _ => Err(format!(
+                "Invalid type '{}', expected 'notebook' or 'folder'",
+                s
+            )),
+        }
+    }
+}
+
+/// A point in time given as YYYY-MM-DD, in milliseconds since the Unix epoch (UTC).
+#[derive(Clone, Copy, Debug)]
+pub struct Date(pub u64);
+
+impl FromStr for Date {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid date '{}', expected YYYY-MM-DD", s);
+        let parts: Vec<&str> = s.split('-').collect();
+        if parts.len() != 3 {
+            return Err(invalid());
+        }
+        let year: i64 = parts[0].parse().map_err(|_| invalid())?;
+        let month: i64 = parts[1].parse().map_err(|_| invalid())?;
+        let day: i64 = parts[2].parse().map_err(|_| invalid())?;
+        if year < 1970
+            || !(1..=12).contains(&month)
+            || !(1..=days_in_month(year, month)).contains(&day)
+        {
+            return Err(invalid());
+        }
+
+        Ok(Date(
+            days_from_civil(year, month, day) as u64 * 24 * 60 * 60 * 1000,
+        ))
+    }
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// See http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
+
+/// Criteria for `restorable find`. Unset criteria match everything.
+#[derive(Default)]
+pub struct Query {
+    pub name: Option<Pattern>,
+    pub kind: Option<Kind>,
+    pub pinned: bool,
+    /// Inclusive lower bound for the last modification
+    pub modified_after: Option<Date>,
+    /// Exclusive upper bound for the last modification
+    pub modified_before: Option<Date>,
+    /// Only search below this folder
+    pub folder: Option<Rc<Node>>,
+    /// "notebook", "pdf" or "epub"
+    pub file_type: Option<String>,
+}
+
+impl Query {
+    pub fn matches(&self, node: &Node, ancestors: &[Rc<Node>], source: &dyn Source) -> bool {
+        if node.metadata.is_none() {
+            return false;
+        }
+        if let Some(name) = &self.name {
+            if !name.matches(node.name()) {
+                return false;
+            }
+        }
+        if let Some(kind) = self.kind {
+            if (kind == Kind::Notebook) != node.is_notebook() {
+                return false;
+            }
+        }
+        if self.pinned && !node.is_pinned() {
+            return false;
+        }
+        if self.modified_after.is_some() || self.modified_before.is_some() {
+            let last_modified = match node.last_modified() {
+                Some(last_modified) => last_modified,
+                None => return false,
+            };
+            if let Some(Date(after)) = self.modified_after {
+                if last_modified < after {
+                    return false;
+                }
+            }
+            if let Some(Date(before)) = self.modified_before {
+                if last_modified >= before {
+                    return false;
+                }
+            }
+        }
+        if let Some(folder) = &self.folder {
+            // The last ancestor is the node itself
+            let parents = &ancestors[..ancestors.len().saturating_sub(1)];
+            if !parents.iter().any(|parent| Rc::ptr_eq(parent, folder)) {
+                return false;
+            }
+        }
+        if let Some(file_type) = &self.file_type {
+            if !node.is_notebook() {
+                return false;
+            }
+            match Content::load(source, &node.id) {
+                Ok(content) => {
+                    // Older notebooks have no file type
+                    let actual = match content.file_type.as_str() {
+                        "" => "notebook",
+                        other => other,
+                    };
+                    if actual != file_type {
+                        return false;
+                    }
+                }
+                Err(_) => return false,
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substring_offsets_point_into_the_original_text() {
+        let pattern = Pattern::new("notes", false).unwrap();
+        // "İ" becomes longer when lowercased
+        assert_eq!(pattern.find("İİ Notes"), Some(5));
+        assert_eq!(Pattern::new("a.b", false).unwrap().find("axb a.b"), Some(4));
+    }
+
+    #[test]
+    fn dates_must_exist() {
+        assert!("2024-02-29".parse::<Date>().is_ok());
+        assert!("2023-02-29".parse::<Date>().is_err());
+        assert!("2024-02-31".parse::<Date>().is_err());
+        assert!("2024-04-31".parse::<Date>().is_err());
+        assert!("2000-02-29".parse::<Date>().is_ok());
+        assert!("1900-02-29".parse::<Date>().is_err());
+    }
+}
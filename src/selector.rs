@@ -0,0 +1,334 @@
+// Selectors address documents and folders in the node tree:
+//
+// - a document ID (UUID), e.g. "0b5c3f5e-2d4f-4b8a-9b65-2a9a1f3c6d7e"
+// - a path of names separated by "/", e.g. "Work/Meeting minutes"
+// - path components may contain the wildcards "*" and "?", e.g. "Work/Meeting*"
+// - "#n" picks the n-th of several siblings with the same name, e.g. "Work/Notes#2"
+
+use std::error::Error;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::node::Node;
+
+#[derive(Debug)]
+pub enum SelectError {
+    NotFound(String),
+    Ambiguous {
+        selector: String,
+        candidates: Vec<String>,
+    },
+}
+
+impl Error for SelectError {}
+
+impl fmt::Display for SelectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SelectError::NotFound(selector) => write!(f, "Cannot find document '{}'", selector),
+            SelectError::Ambiguous {
+                selector,
+                candidates,
+            } => {
+                write!(f, "'{}' matches more than one document:", selector)?;
+                for candidate in candidates {
+                    write!(f, "\n  {}", candidate)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Find the single node matching `selector`.
+pub fn select_one(root: &Node, selector: &str) -> Result<Rc<Node>, SelectError> {
+    let mut matches = select(root, selector);
+    match matches.len() {
+        0 => Err(SelectError::NotFound(selector.to_owned())),
+        1 => Ok(matches.remove(0).1),
+        _ => Err(SelectError::Ambiguous {
+            selector: selector.to_owned(),
+            candidates: matches
+                .iter()
+                .map(|(path, node)| format!("{} ({})", path, node.id))
+                .collect(),
+        }),
+    }
+}
+
+/// Find all nodes matching `selector`, together with a selector that identifies each of them uniquely.
+pub fn select(root: &Node, selector: &str) -> Vec<(String, Rc<Node>)> {
+    let mut by_id = vec![];
+    root.walk(&mut |node, ancestors| {
+        if node.id == selector && !ancestors.is_empty() {
+            by_id.push((
+                unique_path(root, ancestors),
+                ancestors.last().unwrap().clone(),
+            ));
+        }
+    });
+    if !by_id.is_empty() {
+        return by_id;
+    }
+
+    let components: Vec<&str> = selector.split('/').filter(|c| !c.is_empty()).collect();
+    let mut matches = vec![];
+    if !components.is_empty() {
+        select_components(root, &components, &mut vec![], &mut matches);
+    }
+    matches
+        .into_iter()
+        .map(|lineage| (unique_path(root, &lineage), lineage.last().unwrap().clone()))
+        .collect()
+}
+
+fn select_components(
+    parent: &Node,
+    components: &[&str],
+    lineage: &mut Vec<Rc<Node>>,
+    matches: &mut Vec<Vec<Rc<Node>>>,
+) {
+    let children = parent.children.borrow();
+    for child in matching_children(&children, components[0]) {
+        lineage.push(child.clone());
+        if components.len() == 1 {
+            matches.push(lineage.clone());
+        } else {
+            select_components(&child, &components[1..], lineage, matches);
+        }
+        lineage.pop();
+    }
+}
+
+fn matching_children(children: &[Rc<Node>], component: &str) -> Vec<Rc<Node>> {
+    // A literal name wins over the "#n" syntax, in case a name actually ends with "#n"
+    let literal: Vec<Rc<Node>> = children
+        .iter()
+        .filter(|child| child.name() == component)
+        .cloned()
+        .collect();
+    if !literal.is_empty() {
+        return literal;
+    }
+
+    let (pattern, index) = split_index(component);
+    let matching = children
+        .iter()
+        .filter(|child| glob_match(pattern, child.name()))
+        .cloned();
+    match index {
+        None => matching.collect(),
+        Some(index) => matching.skip(index - 1).take(1).collect(),
+    }
+}
+
+fn split_index(component: &str) -> (&str, Option<usize>) {
+    if let Some(position) = component.rfind('#') {
+        if let Ok(index) = component[position + 1..].parse::<usize>() {
+            if index > 0 {
+                return (&component[..position], Some(index));
+            }
+        }
+    }
+
+    (component, None)
+}
+
+/// Match `name` against a pattern where "*" stands for any text and "?" for any character.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if let Some((star, matched)) = backtrack {
+            // Let the last "*" swallow one more character
+            p = star + 1;
+            n = matched + 1;
+            backtrack = Some((star, n));
+        } else {
+            return false;
+        }
+    }
+    while p < pattern.len() && pattern[p] == '*' {
+        p += 1;
+    }
+
+    p == pattern.len()
+}
+
+/// Nodes on the way from `root` down to `node`, including `node` itself.
+pub fn lineage(root: &Node, node: &Rc<Node>) -> Vec<Rc<Node>> {
+    let mut result = vec![];
+    root.walk(&mut |_, ancestors| {
+        if result.is_empty() && matches!(ancestors.last(), Some(last) if Rc::ptr_eq(last, node)) {
+            result = ancestors.clone();
+        }
+    });
+
+    result
+}
+
+/// Name of `node` among its siblings, with a "#n" suffix if other siblings share its name.
+pub fn unique_name(parent: &Node, node: &Node) -> String {
+    let children = parent.children.borrow();
+    let mut namesakes = children.iter().filter(|child| child.name() == node.name());
+    let count = namesakes.clone().count();
+    if count > 1 {
+        if let Some(position) = namesakes.position(|child| child.id == node.id) {
+            return format!("{}#{}", node.name(), position + 1);
+        }
+    }
+
+    node.name().to_owned()
+}
+
+/// Unique names of each node in `lineage`, starting below `root`.
+pub fn unique_names(root: &Node, lineage: &[Rc<Node>]) -> Vec<String> {
+    let mut names = vec![];
+    let mut parent: &Node = root;
+    for node in lineage {
+        names.push(unique_name(parent, node));
+        parent = node;
+    }
+
+    names
+}
+
+/// Selector that addresses the last node of `lineage` and nothing else.
+pub fn unique_path(root: &Node, lineage: &[Rc<Node>]) -> String {
+    unique_names(root, lineage).join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::{parse_nodes, Metadata, NodeType, ParseOptions};
+    use crate::source::{DocumentPaths, Memory};
+
+    fn tree(nodes: &[(&str, NodeType, &str, &str)]) -> Node {
+        let mut source = Memory::new();
+        for (id, r#type, name, parent) in nodes {
+            let metadata = Metadata::new(r#type.clone(), name, parent);
+            source.insert(
+                &DocumentPaths::new(id).metadata(),
+                serde_json::to_vec(&metadata).unwrap(),
+            );
+        }
+
+        parse_nodes(&source, &ParseOptions::default()).unwrap()
+    }
+
+    fn work() -> Node {
+        use NodeType::*;
+        tree(&[
+            ("work", CollectionType, "Work", ""),
+            ("notes-a", DocumentType, "Notes", "work"),
+            ("notes-b", DocumentType, "Notes", "work"),
+            ("minutes", DocumentType, "Meeting minutes", "work"),
+            ("star", DocumentType, "To*do", "work"),
+            ("todo", DocumentType, "Today", "work"),
+            ("hash", DocumentType, "Notes#2", ""),
+        ])
+    }
+
+    fn ids(matches: &[(String, Rc<Node>)]) -> Vec<&str> {
+        matches.iter().map(|(_, node)| node.id.as_str()).collect()
+    }
+
+    #[test]
+    fn globs_match_whole_names() {
+        assert!(glob_match("Notes", "Notes"));
+        assert!(glob_match("No*", "Notes"));
+        assert!(glob_match("*tes", "Notes"));
+        assert!(glob_match("N?t?s", "Notes"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(glob_match("To*", "To*do"));
+        assert!(!glob_match("Note", "Notes"));
+        assert!(!glob_match("otes", "Notes"));
+        assert!(!glob_match("N?tes?", "Notes"));
+        assert!(!glob_match("?", ""));
+        assert!(glob_match("Grüß?", "Grüße"));
+    }
+
+    #[test]
+    fn paths_and_ids_select_nodes() {
+        let root = work();
+        assert_eq!(ids(&select(&root, "Work/Meeting minutes")), vec!["minutes"]);
+        assert_eq!(
+            ids(&select(&root, "/Work//Meeting minutes/")),
+            vec!["minutes"]
+        );
+        assert_eq!(ids(&select(&root, "minutes")), vec!["minutes"]);
+        assert!(select(&root, "Meeting minutes").is_empty());
+        assert!(select(&root, "").is_empty());
+    }
+
+    #[test]
+    fn numbers_pick_among_namesakes() {
+        let root = work();
+        assert_eq!(
+            ids(&select(&root, "Work/Notes")),
+            vec!["notes-a", "notes-b"]
+        );
+        assert_eq!(ids(&select(&root, "Work/Notes#1")), vec!["notes-a"]);
+        assert_eq!(ids(&select(&root, "Work/Notes#2")), vec!["notes-b"]);
+        assert!(select(&root, "Work/Notes#3").is_empty());
+        assert_eq!(ids(&select(&root, "Work/No*#2")), vec!["notes-b"]);
+    }
+
+    #[test]
+    fn literal_names_win_over_patterns() {
+        let root = work();
+        assert_eq!(ids(&select(&root, "Work/To*do")), vec!["star"]);
+        assert_eq!(ids(&select(&root, "Work/To*")), vec!["star", "todo"]);
+        assert_eq!(ids(&select(&root, "Notes#2")), vec!["hash"]);
+    }
+
+    #[test]
+    fn ambiguous_selectors_list_their_candidates() {
+        let root = work();
+        match select_one(&root, "Work/Notes") {
+            Err(SelectError::Ambiguous { candidates, .. }) => assert_eq!(
+                candidates,
+                vec!["Work/Notes#1 (notes-a)", "Work/Notes#2 (notes-b)"]
+            ),
+            Err(other) => panic!("unexpected error {}", other),
+            Ok(node) => panic!("unexpected match {}", node.id),
+        }
+        assert!(matches!(
+            select_one(&root, "Work/Nothing"),
+            Err(SelectError::NotFound(_))
+        ));
+        assert_eq!(select_one(&root, "Work/Notes#2").unwrap().id, "notes-b");
+    }
+
+    #[test]
+    fn unique_names_number_namesakes() {
+        let root = work();
+        let work = root.children.borrow()[1].clone();
+        let names: Vec<String> = work
+            .children
+            .borrow()
+            .iter()
+            .map(|child| unique_name(&work, child))
+            .collect();
+        assert_eq!(
+            names,
+            vec!["Meeting minutes", "Notes#1", "Notes#2", "To*do", "Today"]
+        );
+
+        let notes = select_one(&root, "notes-b").unwrap();
+        let path = unique_path(&root, &lineage(&root, &notes));
+        assert_eq!(path, "Work/Notes#2");
+        assert_eq!(ids(&select(&root, &path)), vec!["notes-b"]);
+    }
+}
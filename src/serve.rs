@@ -0,0 +1,572 @@
+// A small web interface to browse the documents and download them rendered.
+
+use std::collections::hash_map::DefaultHasher;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
+use tiny_http::{Header, Response, ResponseBox, Server};
+
+use restorable::export::{export_notebook, ImageFormat};
+use restorable::node::{parse_nodes, Node, ParseOptions};
+use restorable::notebook::{Content, Notebook};
+use restorable::pages::PageSelection;
+use restorable::selector::{lineage, unique_name, unique_names};
+use restorable::source::{DocumentPaths, Source};
+use restorable::style::RenderOptions;
+use restorable::thumbnail::{thumbnails, NoThumbnail, RENDER_FORMAT, THUMBNAIL_WIDTH};
+
+/// Formats offered for download, as far as they were built in.
+const FORMATS: &[(ImageFormat, bool)] = &[
+    (ImageFormat::Pdf, cfg!(feature = "pdf")),
+    (ImageFormat::Svg, cfg!(feature = "svg")),
+    (ImageFormat::Png, cfg!(feature = "raster")),
+];
+
+pub struct Site<'a> {
+    pub source: &'a dyn Source,
+    pub parse_options: ParseOptions,
+    pub render_options: RenderOptions,
+    /// Where rendered documents are kept, by ID and modification time. Only the renders of
+    /// the latest modification are kept.
+    pub cache: PathBuf,
+    /// Bytes the cache may take; beyond that, the renders used longest ago are removed.
+    pub cache_size: u64,
+}
+
+/// Answer requests on `address` until the server fails. Requests are handled one at a time.
+pub fn serve(site: &Site, address: &str) -> Result<(), Box<dyn Error>> {
+    create_cache(&site.cache)?;
+    let server =
+        Server::http(address).map_err(|e| format!("Cannot listen on {}: {}", address, e))?;
+    println!("Serving on http://{}/", address);
+
+    for request in server.incoming_requests() {
+        let response = match site.respond(request.url()) {
+            Ok(response) => response,
+            Err(e) => {
+                eprintln!("WARNING: {}: {}", request.url(), e);
+                text(500, &e.to_string())
+            }
+        };
+        if let Err(e) = request.respond(response) {
+            eprintln!("WARNING: Failed to send response: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+impl<'a> Site<'a> {
+    fn respond(&self, url: &str) -> Result<ResponseBox, Box<dyn Error>> {
+        let (path, query) = match url.find('?') {
+            Some(position) => (&url[..position], &url[position + 1..]),
+            None => (url, ""),
+        };
+        let root = parse_nodes(self.source, &self.parse_options)?;
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+        match segments.as_slice() {
+            [] => Ok(html(self.folder_page(&root, &root))),
+            ["folder", id] => match find(&root, id) {
+                Some(folder) if !folder.is_notebook() => Ok(html(self.folder_page(&root, &folder))),
+                _ => Ok(text(404, "No such folder")),
+            },
+            ["document", file] => {
+                let (id, extension) = match file.rfind('.') {
+                    Some(position) => (&file[..position], &file[position + 1..]),
+                    None => return Ok(text(404, "No such document")),
+                };
+                match find(&root, id) {
+                    Some(node) if node.is_notebook() => self.document(&node, extension, query),
+                    _ => Ok(text(404, "No such document")),
+                }
+            }
+            ["thumbnail", id] => match find(&root, id) {
+                Some(node) if node.is_notebook() => self.thumbnail(&node),
+                _ => Ok(text(404, "No such document")),
+            },
+            _ => Ok(text(404, "Not found")),
+        }
+    }
+
+    /// The first page, small, from the device's thumbnails when they are up to date.
+    fn thumbnail(&self, node: &Node) -> Result<ResponseBox, Box<dyn Error>> {
+        let name = format!(
+            "{}-{}-thumbnail",
+            node.id,
+            node.last_modified().unwrap_or(0)
+        );
+        for extension in &["jpg", RENDER_FORMAT.extension()] {
+            let path = self.cache.join(format!("{}.{}", name, extension));
+            if path.exists() {
+                touch(&path);
+                return Ok(Response::from_file(File::open(&path)?)
+                    .with_header(content_type(extension))
+                    .boxed());
+            }
+        }
+
+        let thumbnail = match thumbnails(
+            self.source,
+            &node.id,
+            &PageSelection::single(0),
+            THUMBNAIL_WIDTH,
+        ) {
+            Ok(mut thumbnails) => thumbnails.remove(0),
+            Err(e) if e.is::<NoThumbnail>() => return Ok(text(404, &e.to_string())),
+            Err(e) => return Err(e),
+        };
+        let partial = self.cache.join(format!("{}-partial", name));
+        std::fs::write(&partial, &thumbnail.data)?;
+        let path = self.cache.join(format!("{}.{}", name, thumbnail.extension));
+        std::fs::rename(partial, &path)?;
+        self.evict(node, &path);
+
+        Ok(Response::from_data(thumbnail.data)
+            .with_header(content_type(thumbnail.extension))
+            .boxed())
+    }
+
+    /// The whole document, or with `?page=n`, one page; SVG and PNG are always a single page.
+    fn document(
+        &self,
+        node: &Node,
+        extension: &str,
+        query: &str,
+    ) -> Result<ResponseBox, Box<dyn Error>> {
+        let content = Content::load(self.source, &node.id)?;
+        if content.is_imported() {
+            if extension != content.file_type {
+                return Ok(text(
+                    404,
+                    "Imported documents are only available as they are",
+                ));
+            }
+            let data = self
+                .source
+                .read(&DocumentPaths::new(&node.id).document(&content.file_type))?;
+            return Ok(Response::from_data(data)
+                .with_header(content_type(extension))
+                .boxed());
+        }
+
+        let format: ImageFormat = match extension.parse() {
+            Ok(format) => format,
+            Err(_) => return Ok(text(404, "Unknown format")),
+        };
+        let page = query
+            .split('&')
+            .filter_map(|parameter| parameter.strip_prefix("page="))
+            .find_map(|page| page.parse::<usize>().ok());
+        let page = match (page, format.is_single_page()) {
+            (Some(0), _) => return Ok(text(404, "Pages are numbered from 1")),
+            (None, true) => Some(1),
+            (page, _) => page,
+        };
+
+        let path = self.cache.join(format!(
+            "{}-{}-{}-{:x}.{}",
+            node.id,
+            node.last_modified().unwrap_or(0),
+            page.map_or_else(|| "all".to_owned(), |page| page.to_string()),
+            self.options_hash(),
+            extension
+        ));
+        if !path.exists() {
+            let selection = match page {
+                Some(page) => PageSelection::single(page - 1),
+                None => PageSelection::all(),
+            };
+            let notebook = match Notebook::load_pages(self.source, &node.id, &selection) {
+                Ok(notebook) => notebook,
+                Err(e) => return Ok(text(404, &e.to_string())),
+            };
+            // Rendered next to the final path, so an interrupted render is never served
+            let partial = path.with_extension("partial");
+            export_notebook(notebook, format, &partial, false, &self.render_options)?;
+            std::fs::rename(&partial, &path)?;
+            self.evict(node, &path);
+        } else {
+            touch(&path);
+        }
+
+        Ok(Response::from_file(File::open(&path)?)
+            .with_header(content_type(extension))
+            .boxed())
+    }
+
+    /// Remove the renders of earlier modifications of `node`, then the renders used longest
+    /// ago until the cache fits into `cache_size`. `keep`, which was just rendered, stays.
+    fn evict(&self, node: &Node, keep: &Path) {
+        if let Err(e) = self.try_evict(node, keep) {
+            eprintln!("WARNING: Failed to clean up the cache: {}", e);
+        }
+    }
+
+    fn try_evict(&self, node: &Node, keep: &Path) -> std::io::Result<()> {
+        let last_modified = node.last_modified().unwrap_or(0);
+        let prefix = format!("{}-", node.id);
+        let mut files = vec![];
+        for entry in std::fs::read_dir(&self.cache)?.filter_map(Result::ok) {
+            let name = entry.file_name();
+            let modified = name
+                .to_str()
+                .and_then(|name| name.strip_prefix(&prefix))
+                .and_then(|rest| rest.split('-').next())
+                .and_then(|modified| modified.parse::<u64>().ok());
+            if matches!(modified, Some(modified) if modified < last_modified) {
+                remove(&entry.path());
+            } else if let Ok(metadata) = entry.metadata() {
+                if metadata.is_file() {
+                    let used = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                    files.push((used, metadata.len(), entry.path()));
+                }
+            }
+        }
+
+        let mut size: u64 = files.iter().map(|(_, length, _)| length).sum();
+        files.sort();
+        for (_, length, path) in files {
+            if size <= self.cache_size {
+                break;
+            }
+            if path != keep {
+                remove(&path);
+                size -= length;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Renders made with other options are kept apart.
+    fn options_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        format!("{:?}", self.render_options).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn folder_page(&self, root: &Node, folder: &Node) -> String {
+        let mut page = String::new();
+        let title = if folder.id == Node::ROOT_ID {
+            "My files"
+        } else {
+            folder.name()
+        };
+        let _ = write!(
+            page,
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{}</title>{}</head><body>\n",
+            escape(title),
+            STYLE
+        );
+
+        page.push_str("<nav><a href=\"/\">My files</a>");
+        if folder.id != Node::ROOT_ID {
+            if let Some(folder) = find(root, &folder.id) {
+                let ancestors = lineage(root, &folder);
+                for (ancestor, name) in ancestors.iter().zip(unique_names(root, &ancestors)) {
+                    let _ = write!(
+                        page,
+                        " / <a href=\"/folder/{}\">{}</a>",
+                        escape(&ancestor.id),
+                        escape(&name)
+                    );
+                }
+            }
+        }
+        page.push_str("</nav>\n<ul>\n");
+
+        for child in folder.children.borrow().iter() {
+            let name = escape(&unique_name(folder, child));
+            if !child.is_notebook() {
+                let _ = writeln!(
+                    page,
+                    "<li class=\"folder\"><a href=\"/folder/{}\">{}</a></li>",
+                    escape(&child.id),
+                    name
+                );
+                continue;
+            }
+
+            let id = escape(&child.id);
+            let _ = write!(
+                page,
+                "<li class=\"document\"><img src=\"/thumbnail/{}\" loading=\"lazy\" alt=\"\">",
+                id
+            );
+            match Content::load(self.source, &child.id) {
+                Ok(content) if content.is_imported() => {
+                    let _ = write!(
+                        page,
+                        "<span class=\"name\">{}</span> <a href=\"/document/{}.{}\">{}</a>",
+                        name,
+                        id,
+                        content.file_type,
+                        content.file_type.to_uppercase()
+                    );
+                }
+                _ => {
+                    let _ = write!(page, "<span class=\"name\">{}</span>", name);
+                    for (format, _) in FORMATS.iter().filter(|(_, built)| *built) {
+                        let _ = write!(
+                            page,
+                            " <a href=\"/document/{}.{}\">{}</a>",
+                            id,
+                            format.extension(),
+                            format.extension().to_uppercase()
+                        );
+                    }
+                }
+            }
+            page.push_str("</li>\n");
+        }
+        page.push_str("</ul>\n</body></html>\n");
+
+        page
+    }
+}
+
+const STYLE: &str = "<style>\
+body { font-family: sans-serif; margin: 2em; }\
+ul { list-style: none; padding: 0; }\
+li { margin: 0.5em 0; }\
+li.folder a { font-weight: bold; }\
+img { width: 60px; border: 1px solid #ccc; vertical-align: middle; margin-right: 1em; }\
+.name { margin-right: 1em; }\
+</style>";
+
+fn find(root: &Node, id: &str) -> Option<Rc<Node>> {
+    let mut result = None;
+    root.walk(&mut |node, ancestors| {
+        if result.is_none() && node.id == id {
+            result = ancestors.last().cloned();
+        }
+    });
+
+    result
+}
+
+fn html(page: String) -> ResponseBox {
+    Response::from_string(page)
+        .with_header(content_type("html"))
+        .boxed()
+}
+
+fn text(status: u16, message: &str) -> ResponseBox {
+    Response::from_string(message)
+        .with_status_code(status)
+        .boxed()
+}
+
+fn content_type(extension: &str) -> Header {
+    let value = match extension {
+        "html" => "text/html; charset=utf-8",
+        "pdf" => "application/pdf",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" => "image/jpeg",
+        "epub" => "application/epub+zip",
+        _ => "application/octet-stream",
+    };
+    Header::from_bytes(&b"Content-Type"[..], value).unwrap()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Default cache directory, shared by all runs of the user, like `~/.cache/restorable`.
+pub fn default_cache() -> Option<PathBuf> {
+    let home = || std::env::var_os("HOME").map(PathBuf::from);
+    let base = if cfg!(windows) {
+        std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home().map(|home| home.join("Library").join("Caches"))
+    } else {
+        std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| home().map(|home| home.join(".cache")))
+    };
+
+    base.map(|base| base.join("restorable"))
+}
+
+/// Mark a cached file as used, so it is removed later than the others.
+fn touch(path: &Path) {
+    let result = File::options()
+        .append(true)
+        .open(path)
+        .and_then(|file| file.set_modified(SystemTime::now()));
+    if let Err(e) = result {
+        eprintln!("WARNING: Failed to update {:?}: {}", path, e);
+    }
+}
+
+fn remove(path: &Path) {
+    if let Err(e) = std::fs::remove_file(path) {
+        eprintln!("WARNING: Failed to remove {:?}: {}", path, e);
+    }
+}
+
+/// Create `path` so that only the user can read it, as renders of private notebooks end up there.
+fn create_cache(path: &Path) -> std::io::Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+
+    builder.create(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use restorable::linefile::{Layer, LineFile};
+    use restorable::node::{Metadata, NodeType};
+    use restorable::selector::select_one;
+    use restorable::source::Memory;
+    use std::time::Duration;
+
+    /// A notebook "Notes" with an empty page, last modified at `last_modified`.
+    fn notes(last_modified: u64) -> Memory {
+        let paths = DocumentPaths::new("notes");
+        let mut metadata = Metadata::new(NodeType::DocumentType, "Notes", "");
+        metadata.last_modified = last_modified.to_string();
+        let linefile = LineFile {
+            version: 5,
+            layers: vec![Layer { strokes: vec![] }],
+        };
+        let mut page = vec![];
+        linefile.write(&mut page).unwrap();
+
+        let mut source = Memory::new();
+        source.insert(&paths.metadata(), serde_json::to_vec(&metadata).unwrap());
+        source.insert(&paths.content(), r#"{"pages": ["a"]}"#);
+        source.insert(&paths.page("a"), page);
+        source
+    }
+
+    /// An empty cache directory that does not exist yet.
+    fn cache(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("restorable-serve-{}", name));
+        let _ = std::fs::remove_dir_all(&path);
+        path
+    }
+
+    fn site(source: &Memory, cache: PathBuf, cache_size: u64) -> Site<'_> {
+        create_cache(&cache).unwrap();
+        Site {
+            source,
+            parse_options: ParseOptions::default(),
+            render_options: RenderOptions::default(),
+            cache,
+            cache_size,
+        }
+    }
+
+    /// Write `length` bytes to `name`, last used `age` seconds ago.
+    fn cached(cache: &Path, name: &str, length: usize, age: u64) {
+        let path = cache.join(name);
+        std::fs::write(&path, vec![0; length]).unwrap();
+        File::options()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(age))
+            .unwrap();
+    }
+
+    fn names(cache: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(cache)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_the_user_can_read_the_cache() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = cache("private").join("restorable");
+        create_cache(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+    }
+
+    #[test]
+    fn renders_of_earlier_modifications_are_removed() {
+        let source = notes(200);
+        let site = site(&source, cache("modified"), u64::MAX);
+        cached(&site.cache, "notes-100-thumbnail.svg", 10, 0);
+        cached(&site.cache, "notes-100-all-abc.pdf", 10, 0);
+        cached(&site.cache, "notes-300-all-abc.pdf", 10, 0);
+        cached(&site.cache, "other-100-thumbnail.svg", 10, 0);
+
+        site.respond("/thumbnail/notes").unwrap();
+        let rendered = format!("notes-200-thumbnail.{}", RENDER_FORMAT.extension());
+        let mut expected = vec![
+            "notes-300-all-abc.pdf".to_owned(),
+            rendered,
+            "other-100-thumbnail.svg".to_owned(),
+        ];
+        expected.sort();
+        assert_eq!(names(&site.cache), expected);
+    }
+
+    #[test]
+    fn renders_used_longest_ago_are_removed_first() {
+        let source = notes(200);
+        let root = parse_nodes(&source, &ParseOptions::default()).unwrap();
+        let node = select_one(&root, "Notes").unwrap();
+        let site = site(&source, cache("size"), 250);
+        cached(&site.cache, "a-200-all-abc.pdf", 100, 300);
+        cached(&site.cache, "b-200-all-abc.pdf", 100, 100);
+        cached(&site.cache, "c-200-all-abc.pdf", 100, 200);
+        cached(&site.cache, "notes-200-all-abc.pdf", 100, 0);
+
+        site.evict(&node, &site.cache.join("notes-200-all-abc.pdf"));
+        assert_eq!(
+            names(&site.cache),
+            vec!["b-200-all-abc.pdf", "notes-200-all-abc.pdf"]
+        );
+    }
+
+    #[test]
+    fn new_renders_stay_even_when_too_large() {
+        let source = notes(200);
+        let root = parse_nodes(&source, &ParseOptions::default()).unwrap();
+        let node = select_one(&root, "Notes").unwrap();
+        let site = site(&source, cache("large"), 50);
+        cached(&site.cache, "a-200-all-abc.pdf", 10, 100);
+        cached(&site.cache, "notes-200-all-abc.pdf", 100, 0);
+
+        site.evict(&node, &site.cache.join("notes-200-all-abc.pdf"));
+        assert_eq!(names(&site.cache), vec!["notes-200-all-abc.pdf"]);
+    }
+
+    #[test]
+    fn served_renders_count_as_used() {
+        let source = notes(200);
+        let site = site(&source, cache("used"), u64::MAX);
+        let name = format!("notes-200-thumbnail.{}", RENDER_FORMAT.extension());
+        cached(&site.cache, &name, 10, 1000);
+
+        site.respond("/thumbnail/notes").unwrap();
+        let used = std::fs::metadata(site.cache.join(&name))
+            .unwrap()
+            .modified()
+            .unwrap();
+        assert!(used.elapsed().unwrap() < Duration::from_secs(100));
+    }
+}
@@ -0,0 +1,226 @@
+// Reading the xochitl directory from the device over SSH, without mounting it.
+
+use ssh2::{CheckResult, KnownHostFileKind, Session};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::source::Source;
+
+pub struct Sftp {
+    sftp: ssh2::Sftp,
+    root: PathBuf,
+    // Must outlive `sftp`
+    _session: Session,
+}
+
+/// Where the keys of the hosts logged in to are kept, and whether to add unknown ones.
+pub struct KnownHosts {
+    /// An OpenSSH `known_hosts` file; it need not exist yet.
+    pub path: PathBuf,
+    /// Trust and remember the key of a host that is not in the file yet.
+    pub accept_new: bool,
+}
+
+impl KnownHosts {
+    /// `~/.ssh/known_hosts`, shared with OpenSSH.
+    pub fn user() -> Option<KnownHosts> {
+        let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+
+        Some(KnownHosts {
+            path: Path::new(&home).join(".ssh").join("known_hosts"),
+            accept_new: false,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum HostKeyError {
+    /// The host is not in the known hosts file.
+    Unknown { host: String, path: PathBuf },
+    /// The host is known with a different key.
+    Mismatch { host: String, path: PathBuf },
+}
+
+impl Error for HostKeyError {}
+
+impl fmt::Display for HostKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HostKeyError::Unknown { host, path } => write!(
+                f,
+                "The host key of '{}' is unknown, it is not in {}",
+                host,
+                path.display()
+            ),
+            HostKeyError::Mismatch { host, path } => write!(
+                f,
+                "The host key of '{}' does not match the one in {}, someone may be intercepting the connection. \
+                 If the device was reset, remove its old key from the file",
+                host,
+                path.display()
+            ),
+        }
+    }
+}
+
+impl Sftp {
+    /// Log in to `host` ("10.11.99.1" or "host:port") as `user` and read files below `root`.
+    ///
+    /// Without `key`, the keys of the running SSH agent are tried. The host's key must be in
+    /// `known_hosts`, or is added to it if new keys are accepted.
+    pub fn connect(
+        host: &str,
+        user: &str,
+        key: Option<&Path>,
+        root: &str,
+        known_hosts: &KnownHosts,
+    ) -> Result<Sftp, Box<dyn Error>> {
+        let (name, port) = match host.rfind(':') {
+            Some(colon) => (&host[..colon], host[colon + 1..].parse()?),
+            None => (host, 22),
+        };
+        let mut session = Session::new()?;
+        session.set_tcp_stream(TcpStream::connect((name, port))?);
+        session.handshake()?;
+        check_host_key(&session, name, port, known_hosts)?;
+        match key {
+            Some(key) => session.userauth_pubkey_file(user, None, key, None)?,
+            None => session.userauth_agent(user)?,
+        }
+
+        Ok(Sftp {
+            sftp: session.sftp()?,
+            root: PathBuf::from(root),
+            _session: session,
+        })
+    }
+
+    fn full_path(&self, path: &str) -> PathBuf {
+        // The device always uses `/`, also when restorable runs on Windows
+        let root = self.root.to_string_lossy();
+        let root = root.trim_end_matches('/');
+        match path.trim_matches('/') {
+            "" => PathBuf::from(root),
+            path => PathBuf::from(format!("{}/{}", root, path)),
+        }
+    }
+}
+
+fn check_host_key(
+    session: &Session,
+    name: &str,
+    port: u16,
+    known_hosts: &KnownHosts,
+) -> Result<(), Box<dyn Error>> {
+    let (key, key_type) = session
+        .host_key()
+        .ok_or("The server did not send a host key")?;
+    let file = match std::fs::read_to_string(&known_hosts.path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(Box::new(e)),
+    };
+    let mut hosts = session.known_hosts()?;
+    for line in file.lines() {
+        // Comments, and keys libssh2 does not support, like the ones of security keys
+        let _ = hosts.read_str(line, KnownHostFileKind::OpenSSH);
+    }
+
+    // Like OpenSSH, hosts on other ports than 22 are written as "[host]:port"
+    let host = match port {
+        22 => name.to_owned(),
+        port => format!("[{}]:{}", name, port),
+    };
+    match hosts.check_port(name, port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::Mismatch => Err(Box::new(HostKeyError::Mismatch {
+            host,
+            path: known_hosts.path.clone(),
+        })),
+        CheckResult::NotFound if known_hosts.accept_new => {
+            // Appended rather than rewriting the file, which would drop what libssh2 skipped
+            let mut new_hosts = session.known_hosts()?;
+            new_hosts.add(&host, key, "", key_type.into())?;
+            let mut line = String::new();
+            if !file.is_empty() && !file.ends_with('\n') {
+                line.push('\n');
+            }
+            for new_host in new_hosts.hosts()? {
+                line += &new_hosts.write_string(&new_host, KnownHostFileKind::OpenSSH)?;
+            }
+            if let Some(directory) = known_hosts.path.parent() {
+                std::fs::create_dir_all(directory)?;
+            }
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&known_hosts.path)?
+                .write_all(line.as_bytes())?;
+            eprintln!(
+                "Added the host key of '{}' to {}",
+                host,
+                known_hosts.path.display()
+            );
+
+            Ok(())
+        }
+        CheckResult::NotFound => Err(Box::new(HostKeyError::Unknown {
+            host,
+            path: known_hosts.path.clone(),
+        })),
+        CheckResult::Failure => Err(format!(
+            "Could not check the host key of '{}' against {}",
+            host,
+            known_hosts.path.display()
+        )
+        .into()),
+    }
+}
+
+impl Source for Sftp {
+    fn list(&self, path: &str) -> io::Result<Vec<String>> {
+        let entries = self.sftp.readdir(self.full_path(path))?;
+
+        Ok(entries
+            .into_iter()
+            .filter_map(|(path, _)| Some(path.file_name()?.to_str()?.to_owned()))
+            .collect())
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        let mut file = self.sftp.open(self.full_path(path))?;
+        let mut data = vec![];
+        file.read_to_end(&mut data)?;
+
+        Ok(data)
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        let mut file = self.sftp.create(&self.full_path(path))?;
+        file.write_all(data)
+    }
+
+    fn create_dir(&self, path: &str) -> io::Result<()> {
+        let full_path = self.full_path(path);
+        if self.sftp.stat(&full_path).is_ok() {
+            return Ok(());
+        }
+        self.sftp.mkdir(&full_path, 0o755)?;
+
+        Ok(())
+    }
+
+    fn modified(&self, path: &str) -> io::Result<SystemTime> {
+        let seconds = self
+            .sftp
+            .stat(&self.full_path(path))?
+            .mtime
+            .ok_or_else(|| io::Error::other("The server did not send a modification time"))?;
+
+        Ok(UNIX_EPOCH + Duration::from_secs(seconds))
+    }
+}
@@ -0,0 +1,219 @@
+// Where the xochitl data is read from.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// Files of a xochitl directory, addressed by paths relative to its root, like `<id>.content`
+/// or `<id>/<page>.rm`. Components are always separated by `/`.
+pub trait Source {
+    /// Names of the entries in the directory at `path`, `""` for the root.
+    fn list(&self, path: &str) -> io::Result<Vec<String>>;
+
+    /// Content of the file at `path`.
+    fn read(&self, path: &str) -> io::Result<Vec<u8>>;
+
+    /// Create or replace the file at `path`. Sources are read-only unless they override this.
+    fn write(&self, path: &str, _data: &[u8]) -> io::Result<()> {
+        Err(read_only(path))
+    }
+
+    /// Create the directory at `path` and its parents, if they do not exist yet.
+    fn create_dir(&self, path: &str) -> io::Result<()> {
+        Err(read_only(path))
+    }
+
+    /// When the file at `path` was last changed, if the source keeps track of it.
+    fn modified(&self, path: &str) -> io::Result<SystemTime> {
+        Err(io::Error::other(format!(
+            "{}: modification times are not available",
+            path
+        )))
+    }
+}
+
+/// Paths of the files that belong to a document, relative to the root of a source.
+#[derive(Clone, Copy, Debug)]
+pub struct DocumentPaths<'a> {
+    pub id: &'a str,
+}
+
+impl<'a> DocumentPaths<'a> {
+    pub fn new(id: &'a str) -> Self {
+        Self { id }
+    }
+
+    pub fn metadata(&self) -> String {
+        format!("{}.metadata", self.id)
+    }
+
+    pub fn content(&self) -> String {
+        format!("{}.content", self.id)
+    }
+
+    /// Template names of the pages, one per line.
+    pub fn pagedata(&self) -> String {
+        format!("{}.pagedata", self.id)
+    }
+
+    /// Directory of the pages.
+    pub fn pages(&self) -> String {
+        self.id.to_owned()
+    }
+
+    /// The original file of an imported document, `file_type` being "pdf" or "epub".
+    pub fn document(&self, file_type: &str) -> String {
+        format!("{}.{}", self.id, file_type)
+    }
+
+    /// Strokes of a page, in the .lines format.
+    pub fn page(&self, page_id: &str) -> String {
+        format!("{}/{}.rm", self.id, page_id)
+    }
+
+    pub fn page_metadata(&self, page_id: &str) -> String {
+        format!("{}/{}-metadata.json", self.id, page_id)
+    }
+
+    /// Text highlighted in PDFs and EPUBs.
+    pub fn highlights(&self, page_id: &str) -> String {
+        format!("{}.highlights/{}.json", self.id, page_id)
+    }
+
+    /// Preview of a page as JPEG, written by the device when the document is closed.
+    pub fn thumbnail(&self, page_id: &str) -> String {
+        format!("{}.thumbnails/{}.jpg", self.id, page_id)
+    }
+
+    /// Handwriting converted to text on the device.
+    pub fn text_conversion(&self, page_id: &str) -> String {
+        format!("{}.textconversion/{}.json", self.id, page_id)
+    }
+}
+
+/// A directory on the local file system.
+pub struct Directory {
+    root: PathBuf,
+}
+
+impl Directory {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn full_path(&self, path: &str) -> PathBuf {
+        path.split('/')
+            .filter(|component| !component.is_empty())
+            .fold(self.root.clone(), |full_path, component| {
+                full_path.join(component)
+            })
+    }
+}
+
+impl Source for Directory {
+    fn list(&self, path: &str) -> io::Result<Vec<String>> {
+        let mut names = vec![];
+        for entry in std::fs::read_dir(self.full_path(path))? {
+            if let Some(name) = entry?.file_name().to_str() {
+                names.push(name.to_owned());
+            }
+        }
+
+        Ok(names)
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        std::fs::read(self.full_path(path))
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        std::fs::write(self.full_path(path), data)
+    }
+
+    fn create_dir(&self, path: &str) -> io::Result<()> {
+        std::fs::create_dir_all(self.full_path(path))
+    }
+
+    fn modified(&self, path: &str) -> io::Result<SystemTime> {
+        std::fs::metadata(self.full_path(path))?.modified()
+    }
+}
+
+/// Files held in memory, for test fixtures and data unpacked from elsewhere.
+#[derive(Clone, Debug, Default)]
+pub struct Memory {
+    files: RefCell<BTreeMap<String, Vec<u8>>>,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file; directories exist implicitly as long as they contain files.
+    pub fn insert<D: Into<Vec<u8>>>(&mut self, path: &str, data: D) {
+        self.files.get_mut().insert(normalize(path), data.into());
+    }
+}
+
+impl Source for Memory {
+    fn list(&self, path: &str) -> io::Result<Vec<String>> {
+        let prefix = match normalize(path).as_str() {
+            "" => String::new(),
+            path => format!("{}/", path),
+        };
+        let files = self.files.borrow();
+        let names: BTreeSet<&str> = files
+            .keys()
+            .filter_map(|file| file.strip_prefix(&prefix))
+            .filter_map(|rest| rest.split('/').next())
+            .collect();
+        if names.is_empty() && !prefix.is_empty() {
+            return Err(not_found(path));
+        }
+
+        Ok(names.into_iter().map(str::to_owned).collect())
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        self.files
+            .borrow()
+            .get(&normalize(path))
+            .cloned()
+            .ok_or_else(|| not_found(path))
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        self.files
+            .borrow_mut()
+            .insert(normalize(path), data.to_vec());
+
+        Ok(())
+    }
+
+    fn create_dir(&self, _path: &str) -> io::Result<()> {
+        // Directories only exist through the files in them
+        Ok(())
+    }
+}
+
+/// `path` without empty components, so "a//b/" and "a/b" are the same file.
+fn normalize(path: &str) -> String {
+    path.split('/')
+        .filter(|component| !component.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn not_found(path: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{}: not found", path))
+}
+
+fn read_only(path: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("{}: source is read-only", path),
+    )
+}
@@ -0,0 +1,283 @@
+// How pages look, independent of the output format.
+
+use std::borrow::Cow;
+use std::str::FromStr;
+
+use crate::geometry::{union_all, BoundingBox};
+use crate::layout::Layout;
+use crate::linefile::{Color, Pen, Stroke};
+use crate::page::Page;
+
+/// Page size of the device in pixels.
+pub const PAGE_WIDTH: i32 = 1404;
+pub const PAGE_HEIGHT: i32 = 1874;
+
+/// Which part of a page ends up in the output.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Crop {
+    /// The whole page.
+    #[default]
+    Page,
+    /// Everything that was drawn on the page.
+    Content,
+    /// Everything that was drawn on any page of the notebook, so all pages have the same size.
+    Notebook,
+    /// A fixed rectangle in device coordinates.
+    Rect(BoundingBox),
+}
+
+impl FromStr for Crop {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "page" => Ok(Crop::Page),
+            "content" => Ok(Crop::Content),
+            "notebook" => Ok(Crop::Notebook),
+            _ => {
+                let numbers: Vec<f32> = s
+                    .split(',')
+                    .map(|number| number.trim().parse())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| invalid_crop(s))?;
+                match numbers.as_slice() {
+                    &[x, y, width, height] if width > 0.0 && height > 0.0 => {
+                        Ok(Crop::Rect(BoundingBox::new(x, y, width, height)))
+                    }
+                    _ => Err(invalid_crop(s)),
+                }
+            }
+        }
+    }
+}
+
+fn invalid_crop(s: &str) -> String {
+    format!(
+        "Invalid crop '{}', expected 'page', 'content', 'notebook' or 'x,y,width,height'",
+        s
+    )
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RenderOptions {
+    /// Drop points that deviate less than this from a straight line, 0 to keep all points.
+    pub simplify: f32,
+    pub crop: Crop,
+    /// Space around the content when cropping to content.
+    pub margin: f32,
+    /// How pages are put on paper, only used for PDF.
+    pub layout: Layout,
+    /// Width of SVG and PNG images in pixels, the width of the frame if `None`.
+    pub width: Option<f32>,
+}
+
+impl RenderOptions {
+    /// Factor from device pixels to image pixels, for SVG and PNG images of `frame`.
+    pub fn scale(&self, frame: &BoundingBox) -> f32 {
+        match self.width {
+            Some(width) if frame.width() > 0.0 => width / frame.width(),
+            _ => 1.0,
+        }
+    }
+
+    /// The points of `stroke` that are actually drawn.
+    pub fn points<'a>(&self, stroke: &'a Stroke) -> Cow<'a, Stroke> {
+        if self.simplify > 0.0 {
+            Cow::Owned(stroke.simplify(self.simplify))
+        } else {
+            Cow::Borrowed(stroke)
+        }
+    }
+
+    /// The rectangle of each page that is rendered, in device coordinates.
+    ///
+    /// Empty pages keep their full size. Frames never reach beyond the page, and are at
+    /// least `MIN_FRAME_SIZE` wide and high.
+    pub fn frames(&self, pages: &[Page]) -> Vec<BoundingBox> {
+        let full_page = BoundingBox::new(0.0, 0.0, PAGE_WIDTH as f32, PAGE_HEIGHT as f32);
+        let with_margin = |ink: Option<BoundingBox>| {
+            ink.map_or(full_page, |ink| fit_to_page(ink.expand(self.margin)))
+        };
+
+        match self.crop {
+            Crop::Page => vec![full_page; pages.len()],
+            Crop::Content => pages
+                .iter()
+                .map(|page| with_margin(ink_box(page)))
+                .collect(),
+            Crop::Notebook => {
+                let ink = union_all(pages.iter().filter_map(ink_box));
+                vec![with_margin(ink); pages.len()]
+            }
+            Crop::Rect(rect) => vec![fit_to_page(rect); pages.len()],
+        }
+    }
+}
+
+/// Smallest width and height of a frame in pixels, so a dot or a straight line still gives
+/// an image.
+pub const MIN_FRAME_SIZE: f32 = 16.0;
+
+fn fit_to_page(frame: BoundingBox) -> BoundingBox {
+    let (min_x, max_x) = fit_span(frame.min_x, frame.max_x, PAGE_WIDTH as f32);
+    let (min_y, max_y) = fit_span(frame.min_y, frame.max_y, PAGE_HEIGHT as f32);
+
+    BoundingBox {
+        min_x,
+        min_y,
+        max_x,
+        max_y,
+    }
+}
+
+/// Clamp `min..max` to `0..limit`, and grow it around its center to `MIN_FRAME_SIZE`.
+fn fit_span(min: f32, max: f32, limit: f32) -> (f32, f32) {
+    let min = min.max(0.0).min(limit);
+    let max = max.max(0.0).min(limit);
+    if max - min >= MIN_FRAME_SIZE {
+        return (min, max);
+    }
+
+    let start = ((min + max - MIN_FRAME_SIZE) / 2.0)
+        .max(0.0)
+        .min(limit - MIN_FRAME_SIZE);
+    (start, start + MIN_FRAME_SIZE)
+}
+
+/// Area covered by the strokes of `page`, including their width.
+fn ink_box(page: &Page) -> Option<BoundingBox> {
+    union_all(
+        page.linefile
+            .layers
+            .iter()
+            .flat_map(|layer| layer.strokes.iter())
+            .filter(|stroke| !matches!(stroke.pen, Pen::Eraser | Pen::EraseArea | Pen::EraseAll))
+            .filter_map(|stroke| {
+                let half_width = stroke_width(stroke).max(0.0) / 2.0;
+                stroke
+                    .bounding_box()
+                    .map(|bounding_box| bounding_box.expand(half_width))
+            }),
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rgba {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Rgba {
+    pub const BLACK: Rgba = Rgba::opaque(0, 0, 0);
+    pub const GRAY: Rgba = Rgba::opaque(0x88, 0x88, 0x88);
+    pub const WHITE: Rgba = Rgba::opaque(0xFF, 0xFF, 0xFF);
+
+    pub const fn opaque(r: u8, g: u8, b: u8) -> Rgba {
+        Rgba { r, g, b, a: 0xFF }
+    }
+}
+
+pub fn color(stroke: &Stroke) -> Rgba {
+    match stroke.pen {
+        Pen::Highlighter => Rgba {
+            r: 255,
+            g: 255,
+            b: 0,
+            a: 128,
+        },
+        Pen::Eraser => Rgba::WHITE,
+        _ => match stroke.color {
+            Color::Black => Rgba::BLACK,
+            Color::Gray => Rgba::GRAY,
+            Color::White => Rgba::WHITE,
+        },
+    }
+}
+
+pub fn stroke_width(stroke: &Stroke) -> f32 {
+    // Determined by trial and error
+    let w = stroke.width;
+    pen_scale(&stroke.pen) * (w * w * w - 4.0)
+}
+
+fn pen_scale(pen: &Pen) -> f32 {
+    // Determined by trial and error
+    match pen {
+        Pen::Marker => 3.0,
+        Pen::PaintBrush => 2.5,
+        Pen::Pencil => 2.0,
+        Pen::Highlighter => 5.0,
+        Pen::Eraser => 4.0,
+        _ => 1.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linefile::{Layer, LineFile, Segment};
+    use crate::page::{Metadata, Page};
+
+    fn page_with_stroke(points: &[(f32, f32)]) -> Page {
+        let segments = points
+            .iter()
+            .map(|&(x, y)| Segment {
+                x,
+                y,
+                speed: 0.0,
+                direction: 0.0,
+                width: 2.0,
+                pressure: 1.0,
+            })
+            .collect();
+        Page {
+            metadata: Metadata { layers: vec![] },
+            linefile: LineFile {
+                version: 5,
+                layers: vec![Layer {
+                    strokes: vec![Stroke {
+                        pen: Pen::Fineliner,
+                        color: Color::Black,
+                        width: 0.0,
+                        segments,
+                    }],
+                }],
+            },
+        }
+    }
+
+    #[test]
+    fn frames_have_a_minimum_size() {
+        let options = RenderOptions {
+            crop: Crop::Content,
+            ..RenderOptions::default()
+        };
+        let frames = options.frames(&[page_with_stroke(&[(100.0, 100.0), (300.0, 100.0)])]);
+
+        assert!(frames[0].width() >= 200.0);
+        assert!(frames[0].height() >= MIN_FRAME_SIZE);
+    }
+
+    #[test]
+    fn frames_stay_on_the_page() {
+        let options = RenderOptions {
+            crop: Crop::Content,
+            margin: 50.0,
+            ..RenderOptions::default()
+        };
+        let frame = options.frames(&[page_with_stroke(&[(0.0, 0.0)])])[0];
+
+        assert_eq!((frame.min_x, frame.min_y), (0.0, 0.0));
+        assert!(frame.max_x <= PAGE_WIDTH as f32 && frame.max_y <= PAGE_HEIGHT as f32);
+
+        let options = RenderOptions {
+            crop: Crop::Rect(BoundingBox::new(2000.0, 10.0, 50.0, 50.0)),
+            ..RenderOptions::default()
+        };
+        let frame = options.frames(&[page_with_stroke(&[])])[0];
+        assert_eq!(frame.max_x, PAGE_WIDTH as f32);
+        assert_eq!(frame.width(), MIN_FRAME_SIZE);
+    }
+}
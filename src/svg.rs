@@ -0,0 +1,80 @@
+// SVG output without any native dependencies.
+
+use crate::geometry::BoundingBox;
+use crate::linefile::Stroke;
+use crate::page::Page;
+use crate::style::{color, stroke_width, RenderOptions};
+use std::io::Write;
+
+/// Render the part of a single page inside `frame` as SVG image.
+pub fn render_page_svg<Output: Write>(
+    page: &Page,
+    frame: &BoundingBox,
+    output: &mut Output,
+    options: &RenderOptions,
+) -> Result<(), std::io::Error> {
+    writeln!(output, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    let scale = options.scale(frame);
+    writeln!(
+        output,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="{x} {y} {w} {h}">"#,
+        frame.width() * scale,
+        frame.height() * scale,
+        x = frame.min_x,
+        y = frame.min_y,
+        w = frame.width(),
+        h = frame.height()
+    )?;
+    writeln!(
+        output,
+        r#"<rect x="{}" y="{}" width="{}" height="{}" fill="white"/>"#,
+        frame.min_x,
+        frame.min_y,
+        frame.width(),
+        frame.height()
+    )?;
+
+    for layer in page.linefile.layers.iter() {
+        writeln!(output, "<g>")?;
+        for stroke in layer.strokes.iter() {
+            render_stroke(&options.points(stroke), output)?;
+        }
+        writeln!(output, "</g>")?;
+    }
+
+    writeln!(output, "</svg>")?;
+
+    Ok(())
+}
+
+fn render_stroke<Output: Write>(
+    stroke: &Stroke,
+    output: &mut Output,
+) -> Result<(), std::io::Error> {
+    if stroke.segments.is_empty() {
+        return Ok(());
+    }
+
+    let mut data = String::new();
+    for (i, segment) in stroke.segments.iter().enumerate() {
+        let command = if i == 0 { 'M' } else { 'L' };
+        data.push_str(&format!("{}{:.2} {:.2} ", command, segment.x, segment.y));
+    }
+
+    let color = color(stroke);
+    write!(
+        output,
+        r#"<path d="{}" fill="none" stroke="rgb({},{},{})" stroke-width="{:.2}" stroke-linecap="round" stroke-linejoin="round""#,
+        data.trim_end(),
+        color.r,
+        color.g,
+        color.b,
+        stroke_width(stroke)
+    )?;
+    if color.a != 0xFF {
+        write!(output, r#" stroke-opacity="{:.3}""#, color.a as f32 / 255.0)?;
+    }
+    writeln!(output, "/>")?;
+
+    Ok(())
+}
@@ -0,0 +1,150 @@
+use serde_json::Value;
+use std::error::Error;
+
+use crate::json;
+use crate::notebook::Content;
+use crate::scene;
+use crate::source::{DocumentPaths, Source};
+
+/// Text found in a document, and where it was found.
+pub struct PageText {
+    /// Page number, starting at 1. `None` for text that belongs to the whole document.
+    pub page: Option<usize>,
+    pub source: &'static str,
+    pub text: String,
+}
+
+/// Collect typed text, highlights and recognized handwriting of document `id`.
+pub fn document_text(source: &dyn Source, id: &str) -> Result<Vec<PageText>, Box<dyn Error>> {
+    let content = Content::load(source, id)?;
+    let paths = DocumentPaths::new(id);
+    let mut texts = vec![];
+
+    for (index, page_id) in content.page_ids().iter().enumerate() {
+        let page = Some(index + 1);
+
+        let data = source.read(&paths.page(page_id)).unwrap_or_default();
+        if scene::is_v6(&data) {
+            match scene::extract_text(&data) {
+                Ok(blocks) => {
+                    for text in blocks {
+                        texts.push(PageText {
+                            page,
+                            source: "typed",
+                            text,
+                        });
+                    }
+                }
+                Err(e) => eprintln!("WARNING: Failed to read text of page {}: {}", page_id, e),
+            }
+        }
+
+        for (json_path, label, keys) in &[
+            (paths.highlights(page_id), "highlight", &["text"][..]),
+            (
+                paths.text_conversion(page_id),
+                "recognized",
+                &["text", "label"][..],
+            ),
+        ] {
+            if let Ok(value) = json::parse::<Value>(source, json_path) {
+                for text in json_strings(&value, keys) {
+                    texts.push(PageText {
+                        page,
+                        source: label,
+                        text,
+                    });
+                }
+            }
+        }
+    }
+
+    if let Ok(value) = json::parse::<Value>(source, &paths.content()) {
+        for text in json_strings(&value, &["text"]) {
+            texts.push(PageText {
+                page: None,
+                source: "content",
+                text,
+            });
+        }
+    }
+
+    Ok(texts)
+}
+
+/// All string values stored under one of `keys`, at any depth.
+fn json_strings(value: &Value, keys: &[&str]) -> Vec<String> {
+    let mut strings = vec![];
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                match value {
+                    Value::String(string) if keys.contains(&key.as_str()) => {
+                        strings.push(string.clone())
+                    }
+                    _ => strings.extend(json_strings(value, keys)),
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                strings.extend(json_strings(value, keys));
+            }
+        }
+        _ => {}
+    }
+
+    strings
+}
+
+const SNIPPET_LENGTH: usize = 80;
+
+/// Shorten `line` to about `SNIPPET_LENGTH` characters around `position` (a byte offset).
+pub fn snippet(line: &str, position: usize) -> String {
+    let position = position.saturating_sub(line.len() - line.trim_start().len());
+    let line = line.trim();
+    let chars: Vec<(usize, char)> = line.char_indices().collect();
+    if chars.len() <= SNIPPET_LENGTH {
+        return line.to_owned();
+    }
+
+    let center = chars
+        .iter()
+        .position(|(offset, _)| *offset >= position)
+        .unwrap_or(0);
+    let start = center.saturating_sub(SNIPPET_LENGTH / 2);
+    let end = (start + SNIPPET_LENGTH).min(chars.len());
+    let start = end - SNIPPET_LENGTH;
+
+    let mut result: String = chars[start..end].iter().map(|(_, c)| c).collect();
+    if start > 0 {
+        result.insert_str(0, "...");
+    }
+    if end < chars.len() {
+        result.push_str("...");
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snippet_is_centered_on_the_match_after_trimming() {
+        let line = format!(
+            "{}{}needle{}",
+            " ".repeat(40),
+            "a".repeat(100),
+            "b".repeat(100)
+        );
+        let position = line.find("needle").unwrap();
+        let result = snippet(&line, position);
+
+        assert!(result.starts_with("..."));
+        assert!(result.ends_with("..."));
+        let center = result.find("needle").unwrap();
+        assert!((35..=50).contains(&center), "{}", result);
+    }
+}
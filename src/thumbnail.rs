@@ -0,0 +1,286 @@
+// Small previews of pages, taken from the device's cache where possible.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::export::{render_image, ImageFormat};
+use crate::notebook::{Content, Notebook};
+use crate::pages::PageSelection;
+use crate::source::{DocumentPaths, Source};
+use crate::style::RenderOptions;
+
+/// Width of the thumbnails the device writes, in pixels.
+pub const THUMBNAIL_WIDTH: f32 = 280.0;
+
+/// Format of rendered thumbnails; the device's own are always JPEG.
+pub const RENDER_FORMAT: ImageFormat = if cfg!(feature = "raster") {
+    ImageFormat::Png
+} else {
+    ImageFormat::Svg
+};
+
+/// The image file of a thumbnail.
+#[derive(Clone, Debug)]
+pub struct Thumbnail {
+    pub data: Vec<u8>,
+    /// "jpg" for the device's thumbnails, otherwise the extension of `RENDER_FORMAT`.
+    pub extension: &'static str,
+    /// Counting from 1.
+    pub page_number: usize,
+    /// Copied from the `.thumbnails` directory rather than rendered.
+    pub cached: bool,
+}
+
+impl Thumbnail {
+    /// `<id>.<extension>`, or with `numbered`, `<id>-<page number>.<extension>`.
+    pub fn file_name(&self, id: &str, numbered: bool) -> String {
+        if numbered {
+            format!("{}-{}.{}", id, self.page_number, self.extension)
+        } else {
+            format!("{}.{}", id, self.extension)
+        }
+    }
+}
+
+/// Pages of PDFs and EPUBs cannot be rendered, only the strokes on them.
+#[derive(Debug)]
+pub struct NoThumbnail {
+    pub page_number: usize,
+}
+
+impl Error for NoThumbnail {}
+
+impl fmt::Display for NoThumbnail {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "No thumbnail of page {}, the device makes them when the document is opened",
+            self.page_number
+        )
+    }
+}
+
+/// The thumbnail the device wrote for page `page_id` of document `id`, unless the page was
+/// changed after it.
+///
+/// Sources that do not know modification times, like archives, are taken to be consistent.
+pub fn cached_thumbnail(source: &dyn Source, id: &str, page_id: &str) -> Option<Vec<u8>> {
+    let paths = DocumentPaths::new(id);
+    let thumbnail_path = paths.thumbnail(page_id);
+    let data = source.read(&thumbnail_path).ok()?;
+
+    match (
+        source.modified(&thumbnail_path),
+        source.modified(&paths.page(page_id)),
+    ) {
+        (Ok(thumbnail), Ok(page)) if thumbnail < page => None,
+        // Also pages without strokes, whose .rm file is missing
+        _ => Some(data),
+    }
+}
+
+/// Thumbnails of the pages in `selection` of document `id`.
+///
+/// Pages get the device's thumbnail if it is up to date, otherwise they are rendered at
+/// `width` pixels as PNG, or as SVG without the `raster` feature.
+pub fn thumbnails(
+    source: &dyn Source,
+    id: &str,
+    selection: &PageSelection,
+    width: f32,
+) -> Result<Vec<Thumbnail>, Box<dyn Error>> {
+    let content = Content::load(source, id)?;
+    let page_ids = content.page_ids();
+    if page_ids.is_empty() {
+        return Err(Box::new(NoThumbnail { page_number: 1 }));
+    }
+    let indices = selection.indices(page_ids.len())?;
+
+    let options = RenderOptions {
+        width: Some(width),
+        ..RenderOptions::default()
+    };
+
+    let mut thumbnails = vec![];
+    for index in &indices {
+        let page_number = index + 1;
+        let cached = match cached_thumbnail(source, id, &page_ids[*index]) {
+            // An outdated thumbnail still shows the PDF or EPUB page, which is not rendered
+            None if content.is_imported() => source
+                .read(&DocumentPaths::new(id).thumbnail(&page_ids[*index]))
+                .ok(),
+            cached => cached,
+        };
+        if let Some(data) = cached {
+            thumbnails.push(Thumbnail {
+                data,
+                extension: "jpg",
+                page_number,
+                cached: true,
+            });
+            continue;
+        }
+
+        if content.is_imported() {
+            return Err(Box::new(NoThumbnail { page_number }));
+        }
+        let mut notebook = Notebook::load_pages(source, id, &PageSelection::single(*index))?;
+        thumbnails.push(Thumbnail {
+            data: render_image(notebook.pages.remove(0), RENDER_FORMAT, &options)?,
+            extension: RENDER_FORMAT.extension(),
+            page_number,
+            cached: false,
+        });
+    }
+
+    Ok(thumbnails)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linefile::{Layer, LineFile};
+    use crate::source::Memory;
+    use std::collections::HashMap;
+    use std::io;
+    use std::time::{Duration, SystemTime};
+
+    /// `Memory` with modification times, in seconds.
+    #[derive(Default)]
+    struct Timed {
+        files: Memory,
+        modified: HashMap<String, u64>,
+    }
+
+    impl Timed {
+        fn insert<D: Into<Vec<u8>>>(&mut self, path: &str, data: D, modified: u64) {
+            self.files.insert(path, data);
+            self.modified.insert(path.to_owned(), modified);
+        }
+    }
+
+    impl Source for Timed {
+        fn list(&self, path: &str) -> io::Result<Vec<String>> {
+            self.files.list(path)
+        }
+
+        fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+            self.files.read(path)
+        }
+
+        fn modified(&self, path: &str) -> io::Result<SystemTime> {
+            match self.modified.get(path) {
+                Some(seconds) => Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(*seconds)),
+                None => self.files.modified(path),
+            }
+        }
+    }
+
+    fn linefile_data() -> Vec<u8> {
+        let linefile = LineFile {
+            version: 5,
+            layers: vec![Layer { strokes: vec![] }],
+        };
+        let mut data = vec![];
+        linefile.write(&mut data).unwrap();
+
+        data
+    }
+
+    /// A notebook with pages "a" and "b", whose thumbnails were written at 100.
+    fn notes(page_modified: &[u64]) -> Timed {
+        let paths = DocumentPaths::new("notes");
+        let mut source = Timed::default();
+        source.insert(&paths.content(), r#"{"pages": ["a", "b"]}"#, 0);
+        for (page_id, modified) in ["a", "b"].iter().zip(page_modified) {
+            source.insert(&paths.page(page_id), linefile_data(), *modified);
+            source.insert(&paths.thumbnail(page_id), "jpeg", 100);
+        }
+        source
+    }
+
+    #[test]
+    fn thumbnails_are_used_until_the_page_changes() {
+        let source = notes(&[50, 150]);
+        assert_eq!(
+            cached_thumbnail(&source, "notes", "a"),
+            Some(b"jpeg".to_vec())
+        );
+        assert_eq!(cached_thumbnail(&source, "notes", "b"), None);
+        assert_eq!(cached_thumbnail(&source, "notes", "c"), None);
+    }
+
+    #[test]
+    fn thumbnails_without_times_are_used() {
+        let source = notes(&[50, 150]);
+        // Neither the thumbnail nor the page have a time
+        assert_eq!(
+            cached_thumbnail(&source.files, "notes", "b"),
+            Some(b"jpeg".to_vec())
+        );
+
+        // A page without strokes has no .rm file
+        let mut source = Timed::default();
+        let paths = DocumentPaths::new("notes");
+        source.insert(&paths.thumbnail("a"), "jpeg", 100);
+        assert_eq!(
+            cached_thumbnail(&source, "notes", "a"),
+            Some(b"jpeg".to_vec())
+        );
+    }
+
+    #[cfg(any(feature = "svg", feature = "raster"))]
+    #[test]
+    fn outdated_thumbnails_are_rendered() {
+        let source = notes(&[50, 150]);
+        let thumbnails = thumbnails(&source, "notes", &PageSelection::all(), 140.0).unwrap();
+        let summary: Vec<(usize, bool, &str)> = thumbnails
+            .iter()
+            .map(|thumbnail| (thumbnail.page_number, thumbnail.cached, thumbnail.extension))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![(1, true, "jpg"), (2, false, RENDER_FORMAT.extension())]
+        );
+    }
+
+    #[test]
+    fn imported_documents_keep_outdated_thumbnails() {
+        let mut source = notes(&[50, 150]);
+        let paths = DocumentPaths::new("notes");
+        source.insert(
+            &paths.content(),
+            r#"{"fileType": "pdf", "pages": ["a", "b", "c"]}"#,
+            0,
+        );
+
+        let selection: PageSelection = "1-2".parse().unwrap();
+        let thumbnails = thumbnails(&source, "notes", &selection, 140.0).unwrap();
+        assert!(thumbnails.iter().all(|thumbnail| thumbnail.cached));
+
+        let selection: PageSelection = "3".parse().unwrap();
+        let error = super::thumbnails(&source, "notes", &selection, 140.0).unwrap_err();
+        assert_eq!(error.downcast_ref::<NoThumbnail>().unwrap().page_number, 3);
+    }
+
+    #[test]
+    fn every_page_is_numbered_with_all_pages() {
+        let source = notes(&[50, 50]);
+        let names: Vec<String> = thumbnails(&source, "notes", &PageSelection::all(), 140.0)
+            .unwrap()
+            .iter()
+            .map(|thumbnail| thumbnail.file_name("notes", true))
+            .collect();
+        assert_eq!(names, vec!["notes-1.jpg", "notes-2.jpg"]);
+
+        let paths = DocumentPaths::new("single");
+        let mut source = Timed::default();
+        source.insert(&paths.content(), r#"{"pages": ["a"]}"#, 0);
+        source.insert(&paths.thumbnail("a"), "jpeg", 100);
+        let thumbnail = thumbnails(&source, "single", &PageSelection::all(), 140.0)
+            .unwrap()
+            .remove(0);
+        assert_eq!(thumbnail.file_name("single", true), "single-1.jpg");
+        assert_eq!(thumbnail.file_name("single", false), "single.jpg");
+    }
+}
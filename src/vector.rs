@@ -0,0 +1,934 @@
+// Turning vector drawings into strokes, so diagrams drawn on the desktop become editable ink.
+
+use lopdf::{Document, Object};
+use std::error::Error;
+use std::f32::consts::PI;
+use std::fmt;
+use std::path::Path;
+
+use crate::layout::DPI;
+use crate::linefile::{Color, Pen, Segment, Stroke};
+use crate::style::{PAGE_HEIGHT, PAGE_WIDTH};
+
+/// Curves are split into straight segments about this long, in device pixels.
+const SEGMENT_LENGTH: f32 = 4.0;
+
+/// Arcs and ellipses are split into segments of this angle.
+const ARC_STEP: f32 = PI / 32.0;
+
+/// How imported paths are drawn.
+#[derive(Clone, Debug)]
+pub struct StrokeStyle {
+    pub pen: Pen,
+    pub color: Color,
+    /// Base width as stored in .lines files: 1.875 is thin, 2.0 medium, 2.125 thick.
+    pub width: f32,
+}
+
+impl Default for StrokeStyle {
+    fn default() -> Self {
+        Self {
+            pen: Pen::Fineliner,
+            color: Color::Black,
+            width: 2.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct VectorError(pub String);
+
+impl Error for VectorError {}
+
+impl fmt::Display for VectorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// An affine transformation, mapping (x, y) to (a x + c y + e, b x + d y + f) as in SVG and PDF.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub d: f32,
+    pub e: f32,
+    pub f: f32,
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform::new(1.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+
+    pub const fn new(a: f32, b: f32, c: f32, d: f32, e: f32, f: f32) -> Self {
+        Self { a, b, c, d, e, f }
+    }
+
+    pub fn translate(x: f32, y: f32) -> Self {
+        Self::new(1.0, 0.0, 0.0, 1.0, x, y)
+    }
+
+    pub fn scale(x: f32, y: f32) -> Self {
+        Self::new(x, 0.0, 0.0, y, 0.0, 0.0)
+    }
+
+    /// Rotation by `degrees`, clockwise on screen.
+    pub fn rotate(degrees: f32) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Self::new(cos, sin, -sin, cos, 0.0, 0.0)
+    }
+
+    /// First `inner`, then `self`.
+    pub fn multiply(&self, inner: &Transform) -> Transform {
+        Transform::new(
+            self.a * inner.a + self.c * inner.b,
+            self.b * inner.a + self.d * inner.b,
+            self.a * inner.c + self.c * inner.d,
+            self.b * inner.c + self.d * inner.d,
+            self.a * inner.e + self.c * inner.f + self.e,
+            self.b * inner.e + self.d * inner.f + self.f,
+        )
+    }
+
+    pub fn apply(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        (
+            self.a * x + self.c * y + self.e,
+            self.b * x + self.d * y + self.f,
+        )
+    }
+}
+
+/// Read the strokes of the file `data`: one page for an SVG, one per page for a PDF.
+///
+/// The extension of `path` tells which of the two it is.
+pub fn vector_pages(
+    path: &Path,
+    data: &[u8],
+    style: &StrokeStyle,
+) -> Result<Vec<Vec<Stroke>>, Box<dyn Error>> {
+    let extension = path.extension().map_or_else(String::new, |extension| {
+        extension.to_string_lossy().to_lowercase()
+    });
+    match extension.as_str() {
+        "svg" => Ok(vec![svg_strokes(std::str::from_utf8(data)?, style)?]),
+        "pdf" => pdf_strokes(data, style),
+        _ => Err(Box::new(VectorError(format!(
+            "Cannot read strokes from {:?}, only from .svg and .pdf files",
+            path
+        )))),
+    }
+}
+
+/// Strokes for the shapes of an SVG document, at their real size unless that exceeds the page.
+///
+/// Every path, line, polyline, polygon, rectangle, circle and ellipse becomes one stroke per
+/// subpath, filled shapes by their outline. Text, images and `<use>` references are skipped.
+pub fn svg_strokes(svg: &str, style: &StrokeStyle) -> Result<Vec<Stroke>, Box<dyn Error>> {
+    let document = roxmltree::Document::parse(svg)?;
+    let root = document.root_element();
+
+    let view_box =
+        root.attribute("viewBox")
+            .map(numbers)
+            .and_then(|values| match values.as_slice() {
+                &[x, y, width, height] if width > 0.0 && height > 0.0 => {
+                    Some((x, y, width, height))
+                }
+                _ => None,
+            });
+    let width = root.attribute("width").and_then(length);
+    let height = root.attribute("height").and_then(length);
+    let (width, height) = match (width, height, view_box) {
+        (Some(width), Some(height), _) => (width, height),
+        (_, _, Some((_, _, width, height))) => (width, height),
+        _ => (PAGE_WIDTH as f32, PAGE_HEIGHT as f32),
+    };
+    let user_space = match view_box {
+        Some((x, y, view_width, view_height)) => {
+            let scale = (width / view_width).min(height / view_height);
+            Transform::scale(scale, scale).multiply(&Transform::translate(-x, -y))
+        }
+        None => Transform::IDENTITY,
+    };
+    // SVG pixels are 1/96 inch
+    let scale = fit(width * DPI / 96.0, height * DPI / 96.0) * DPI / 96.0;
+    let transform = Transform::scale(scale, scale).multiply(&user_space);
+
+    let mut lines = vec![];
+    collect_svg(root, &transform, &mut lines)?;
+
+    Ok(lines.into_iter().map(|line| stroke(line, style)).collect())
+}
+
+/// Elements whose content is not drawn where it is.
+const SKIPPED_ELEMENTS: &[&str] = &[
+    "defs", "clipPath", "mask", "marker", "pattern", "symbol", "style", "script", "title", "desc",
+    "metadata", "text",
+];
+
+fn collect_svg(
+    node: roxmltree::Node,
+    transform: &Transform,
+    lines: &mut Vec<Vec<(f32, f32)>>,
+) -> Result<(), VectorError> {
+    for child in node.children().filter(|child| child.is_element()) {
+        let name = child.tag_name().name();
+        if SKIPPED_ELEMENTS.contains(&name) || child.attribute("display") == Some("none") {
+            continue;
+        }
+        let transform = match child.attribute("transform") {
+            Some(value) => transform.multiply(&parse_transform(value)?),
+            None => *transform,
+        };
+        let number = |attribute: &str| child.attribute(attribute).and_then(length).unwrap_or(0.0);
+
+        let mut path = PathBuilder::new(transform);
+        match name {
+            "g" | "a" | "svg" | "switch" => collect_svg(child, &transform, lines)?,
+            "path" => parse_path(child.attribute("d").unwrap_or(""), &mut path)?,
+            "line" => {
+                path.move_to((number("x1"), number("y1")));
+                path.line_to((number("x2"), number("y2")));
+            }
+            "polyline" | "polygon" => {
+                let values = numbers(child.attribute("points").unwrap_or(""));
+                for (index, point) in values.chunks_exact(2).enumerate() {
+                    match index {
+                        0 => path.move_to((point[0], point[1])),
+                        _ => path.line_to((point[0], point[1])),
+                    }
+                }
+                if name == "polygon" {
+                    path.close();
+                }
+            }
+            "rect" => {
+                let (x, y) = (number("x"), number("y"));
+                let (width, height) = (number("width"), number("height"));
+                path.rectangle(x, y, width, height);
+            }
+            "circle" => {
+                let r = number("r");
+                path.ellipse((number("cx"), number("cy")), r, r);
+            }
+            "ellipse" => path.ellipse((number("cx"), number("cy")), number("rx"), number("ry")),
+            _ => {}
+        }
+        lines.extend(path.take());
+    }
+
+    Ok(())
+}
+
+/// Strokes for the paths drawn on each page of a PDF, at their real size unless that exceeds
+/// the page. Text, images and form XObjects are skipped.
+pub fn pdf_strokes(data: &[u8], style: &StrokeStyle) -> Result<Vec<Vec<Stroke>>, Box<dyn Error>> {
+    let document = Document::load_mem(data)?;
+
+    let mut pages = vec![];
+    for page_id in document.get_pages().values() {
+        let [left, bottom, right, top] =
+            media_box(&document, *page_id).unwrap_or([0.0, 0.0, 612.0, 792.0]);
+        // PDF points are 1/72 inch, and y grows upwards
+        let scale = fit((right - left) * DPI / 72.0, (top - bottom) * DPI / 72.0) * DPI / 72.0;
+        let page_transform = Transform::new(scale, 0.0, 0.0, -scale, -left * scale, top * scale);
+
+        let content = document.get_and_decode_page_content(*page_id)?;
+        let mut path = PathBuilder::new(page_transform);
+        let mut saved = vec![];
+        let mut lines = vec![];
+        for operation in &content.operations {
+            let operands: Vec<f32> = operation.operands.iter().filter_map(number).collect();
+            match (operation.operator.as_str(), operands.as_slice()) {
+                ("q", _) => saved.push(path.transform),
+                ("Q", _) => path.transform = saved.pop().unwrap_or(page_transform),
+                ("cm", &[a, b, c, d, e, f]) => {
+                    path.transform = path.transform.multiply(&Transform::new(a, b, c, d, e, f))
+                }
+                ("m", &[x, y]) => path.move_to((x, y)),
+                ("l", &[x, y]) => path.line_to((x, y)),
+                ("c", &[x1, y1, x2, y2, x3, y3]) => path.cubic_to((x1, y1), (x2, y2), (x3, y3)),
+                ("v", &[x2, y2, x3, y3]) => path.cubic_to(path.current, (x2, y2), (x3, y3)),
+                ("y", &[x1, y1, x3, y3]) => path.cubic_to((x1, y1), (x3, y3), (x3, y3)),
+                ("h", _) => path.close(),
+                ("re", &[x, y, width, height]) => path.rectangle(x, y, width, height),
+                ("s", _) | ("b", _) | ("b*", _) => {
+                    path.close();
+                    lines.extend(path.take());
+                }
+                ("S", _) | ("f", _) | ("F", _) | ("f*", _) | ("B", _) | ("B*", _) => {
+                    lines.extend(path.take())
+                }
+                ("n", _) => {
+                    // Ends a path that is only used for clipping
+                    path.take();
+                }
+                _ => {}
+            }
+        }
+        pages.push(lines.into_iter().map(|line| stroke(line, style)).collect());
+    }
+
+    Ok(pages)
+}
+
+fn media_box(document: &Document, page_id: lopdf::ObjectId) -> Option<[f32; 4]> {
+    // Pages inherit the media box from their ancestors in the page tree
+    let mut dictionary = document.get_dictionary(page_id).ok()?;
+    loop {
+        if let Ok(media_box) = dictionary.get(b"MediaBox") {
+            let (_, media_box) = document.dereference(media_box).ok()?;
+            let values: Vec<f32> = media_box
+                .as_array()
+                .ok()?
+                .iter()
+                .filter_map(number)
+                .collect();
+            return match values.as_slice() {
+                &[left, bottom, right, top] if right > left && top > bottom => {
+                    Some([left, bottom, right, top])
+                }
+                _ => None,
+            };
+        }
+        let parent = dictionary
+            .get(b"Parent")
+            .and_then(Object::as_reference)
+            .ok()?;
+        dictionary = document.get_dictionary(parent).ok()?;
+    }
+}
+
+fn number(object: &Object) -> Option<f32> {
+    match object {
+        Object::Integer(value) => Some(*value as f32),
+        Object::Real(value) => Some(*value as f32),
+        _ => None,
+    }
+}
+
+/// Factor that shrinks a drawing of this size in device pixels to fit on the page.
+fn fit(width: f32, height: f32) -> f32 {
+    (PAGE_WIDTH as f32 / width)
+        .min(PAGE_HEIGHT as f32 / height)
+        .min(1.0)
+}
+
+fn stroke(points: Vec<(f32, f32)>, style: &StrokeStyle) -> Stroke {
+    Stroke {
+        pen: style.pen.clone(),
+        color: style.color.clone(),
+        width: style.width,
+        segments: points
+            .into_iter()
+            .map(|(x, y)| Segment {
+                x,
+                y,
+                speed: 0.0,
+                direction: 0.0,
+                width: style.width,
+                pressure: 1.0,
+            })
+            .collect(),
+    }
+}
+
+/// Collects subpaths as lines in device pixels. Points are given in user space and mapped
+/// through `transform`; curves are flattened after mapping, which is exact for Béziers.
+struct PathBuilder {
+    transform: Transform,
+    /// Current point in user space.
+    current: (f32, f32),
+    start: (f32, f32),
+    line: Vec<(f32, f32)>,
+    lines: Vec<Vec<(f32, f32)>>,
+}
+
+impl PathBuilder {
+    fn new(transform: Transform) -> Self {
+        Self {
+            transform,
+            current: (0.0, 0.0),
+            start: (0.0, 0.0),
+            line: vec![],
+            lines: vec![],
+        }
+    }
+
+    fn move_to(&mut self, point: (f32, f32)) {
+        self.finish_line();
+        self.line.push(self.transform.apply(point));
+        self.current = point;
+        self.start = point;
+    }
+
+    fn line_to(&mut self, point: (f32, f32)) {
+        if self.line.is_empty() {
+            self.line.push(self.transform.apply(self.current));
+        }
+        self.line.push(self.transform.apply(point));
+        self.current = point;
+    }
+
+    fn cubic_to(&mut self, control1: (f32, f32), control2: (f32, f32), point: (f32, f32)) {
+        let p0 = self.transform.apply(self.current);
+        let p1 = self.transform.apply(control1);
+        let p2 = self.transform.apply(control2);
+        let p3 = self.transform.apply(point);
+        if self.line.is_empty() {
+            self.line.push(p0);
+        }
+        let steps = steps(distance(p0, p1) + distance(p1, p2) + distance(p2, p3));
+        for step in 1..=steps {
+            let t = step as f32 / steps as f32;
+            let u = 1.0 - t;
+            let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+            self.line.push((
+                a * p0.0 + b * p1.0 + c * p2.0 + d * p3.0,
+                a * p0.1 + b * p1.1 + c * p2.1 + d * p3.1,
+            ));
+        }
+        self.current = point;
+    }
+
+    fn quad_to(&mut self, control: (f32, f32), point: (f32, f32)) {
+        // The same curve as a cubic Bézier
+        let (x0, y0) = self.current;
+        let control1 = (
+            x0 + 2.0 / 3.0 * (control.0 - x0),
+            y0 + 2.0 / 3.0 * (control.1 - y0),
+        );
+        let control2 = (
+            point.0 + 2.0 / 3.0 * (control.0 - point.0),
+            point.1 + 2.0 / 3.0 * (control.1 - point.1),
+        );
+        self.cubic_to(control1, control2, point);
+    }
+
+    /// Elliptical arc as in SVG, see https://www.w3.org/TR/SVG11/implnote.html#ArcImplementationNotes
+    fn arc_to(
+        &mut self,
+        (rx, ry): (f32, f32),
+        rotation: f32,
+        large_arc: bool,
+        sweep: bool,
+        point: (f32, f32),
+    ) {
+        let (x1, y1) = self.current;
+        let (x2, y2) = point;
+        if (x1, y1) == (x2, y2) {
+            return;
+        }
+        let (mut rx, mut ry) = (rx.abs(), ry.abs());
+        if rx == 0.0 || ry == 0.0 {
+            self.line_to(point);
+            return;
+        }
+
+        let (sin, cos) = rotation.to_radians().sin_cos();
+        let (dx, dy) = ((x1 - x2) / 2.0, (y1 - y2) / 2.0);
+        let (x1p, y1p) = (cos * dx + sin * dy, -sin * dx + cos * dy);
+        let lambda = (x1p * x1p) / (rx * rx) + (y1p * y1p) / (ry * ry);
+        if lambda > 1.0 {
+            rx *= lambda.sqrt();
+            ry *= lambda.sqrt();
+        }
+        let numerator = rx * rx * ry * ry - rx * rx * y1p * y1p - ry * ry * x1p * x1p;
+        let denominator = rx * rx * y1p * y1p + ry * ry * x1p * x1p;
+        let sign = if large_arc == sweep { -1.0 } else { 1.0 };
+        let coefficient = sign * (numerator / denominator).max(0.0).sqrt();
+        let (cxp, cyp) = (coefficient * rx * y1p / ry, -coefficient * ry * x1p / rx);
+        let center = (
+            cos * cxp - sin * cyp + (x1 + x2) / 2.0,
+            sin * cxp + cos * cyp + (y1 + y2) / 2.0,
+        );
+
+        let angle = |(ux, uy): (f32, f32), (vx, vy): (f32, f32)| {
+            (ux * vy - uy * vx).atan2(ux * vx + uy * vy)
+        };
+        let start_vector = ((x1p - cxp) / rx, (y1p - cyp) / ry);
+        let end_vector = ((-x1p - cxp) / rx, (-y1p - cyp) / ry);
+        let start = angle((1.0, 0.0), start_vector);
+        let mut sweep_angle = angle(start_vector, end_vector);
+        if !sweep && sweep_angle > 0.0 {
+            sweep_angle -= 2.0 * PI;
+        } else if sweep && sweep_angle < 0.0 {
+            sweep_angle += 2.0 * PI;
+        }
+
+        let steps = ((sweep_angle.abs() / ARC_STEP).ceil() as usize).max(1);
+        for step in 1..steps {
+            let theta = start + sweep_angle * step as f32 / steps as f32;
+            let (x, y) = (rx * theta.cos(), ry * theta.sin());
+            self.line_to((center.0 + cos * x - sin * y, center.1 + sin * x + cos * y));
+        }
+        // Exactly at the end, so following relative commands start there
+        self.line_to(point);
+    }
+
+    fn rectangle(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        self.move_to((x, y));
+        self.line_to((x + width, y));
+        self.line_to((x + width, y + height));
+        self.line_to((x, y + height));
+        self.close();
+    }
+
+    fn ellipse(&mut self, (cx, cy): (f32, f32), rx: f32, ry: f32) {
+        if rx <= 0.0 || ry <= 0.0 {
+            return;
+        }
+        self.move_to((cx + rx, cy));
+        let steps = (2.0 * PI / ARC_STEP).ceil() as usize;
+        for step in 1..steps {
+            let theta = 2.0 * PI * step as f32 / steps as f32;
+            self.line_to((cx + rx * theta.cos(), cy + ry * theta.sin()));
+        }
+        self.close();
+    }
+
+    fn close(&mut self) {
+        if !self.line.is_empty() {
+            let start = self.start;
+            self.line_to(start);
+        }
+        self.finish_line();
+        self.current = self.start;
+    }
+
+    fn finish_line(&mut self) {
+        let line = std::mem::take(&mut self.line);
+        if line.len() >= 2 {
+            self.lines.push(line);
+        }
+    }
+
+    /// The lines collected since the last call.
+    fn take(&mut self) -> Vec<Vec<(f32, f32)>> {
+        self.finish_line();
+        std::mem::take(&mut self.lines)
+    }
+}
+
+fn distance((x0, y0): (f32, f32), (x1, y1): (f32, f32)) -> f32 {
+    ((x1 - x0).powi(2) + (y1 - y0).powi(2)).sqrt()
+}
+
+fn steps(length: f32) -> usize {
+    ((length / SEGMENT_LENGTH).ceil() as usize).clamp(1, 64)
+}
+
+/// Which control point the next smooth curve reflects.
+#[derive(Clone, Copy)]
+enum Control {
+    None,
+    Cubic((f32, f32)),
+    Quadratic((f32, f32)),
+}
+
+/// Follow the path data of an SVG `<path>` element.
+fn parse_path(data: &str, path: &mut PathBuilder) -> Result<(), VectorError> {
+    let mut data = PathData {
+        bytes: data.as_bytes(),
+        position: 0,
+    };
+    let mut previous = Control::None;
+
+    while let Some(command) = data.command()? {
+        let relative = command.is_ascii_lowercase();
+        let mut command = command.to_ascii_uppercase();
+        loop {
+            let (x, y) = path.current;
+            let offset = |(px, py): (f32, f32)| {
+                if relative {
+                    (x + px, y + py)
+                } else {
+                    (px, py)
+                }
+            };
+            let mut control = Control::None;
+            match command {
+                b'M' => {
+                    path.move_to(offset(data.point()?));
+                    // Further coordinate pairs are lines
+                    command = b'L';
+                }
+                b'L' => path.line_to(offset(data.point()?)),
+                b'H' => {
+                    let value = data.number()?;
+                    path.line_to((if relative { x + value } else { value }, y));
+                }
+                b'V' => {
+                    let value = data.number()?;
+                    path.line_to((x, if relative { y + value } else { value }));
+                }
+                b'C' => {
+                    let control1 = offset(data.point()?);
+                    let control2 = offset(data.point()?);
+                    path.cubic_to(control1, control2, offset(data.point()?));
+                    control = Control::Cubic(control2);
+                }
+                b'S' => {
+                    let control1 = match previous {
+                        Control::Cubic((cx, cy)) => (2.0 * x - cx, 2.0 * y - cy),
+                        _ => (x, y),
+                    };
+                    let control2 = offset(data.point()?);
+                    path.cubic_to(control1, control2, offset(data.point()?));
+                    control = Control::Cubic(control2);
+                }
+                b'Q' => {
+                    let control1 = offset(data.point()?);
+                    path.quad_to(control1, offset(data.point()?));
+                    control = Control::Quadratic(control1);
+                }
+                b'T' => {
+                    let control1 = match previous {
+                        Control::Quadratic((cx, cy)) => (2.0 * x - cx, 2.0 * y - cy),
+                        _ => (x, y),
+                    };
+                    path.quad_to(control1, offset(data.point()?));
+                    control = Control::Quadratic(control1);
+                }
+                b'A' => {
+                    let radii = data.point()?;
+                    let rotation = data.number()?;
+                    let large_arc = data.flag()?;
+                    let sweep = data.flag()?;
+                    path.arc_to(radii, rotation, large_arc, sweep, offset(data.point()?));
+                }
+                b'Z' => path.close(),
+                _ => {
+                    return Err(VectorError(format!(
+                        "Unknown path command '{}'",
+                        command as char
+                    )))
+                }
+            }
+            previous = control;
+            if command == b'Z' || !data.at_number() {
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the commands and numbers of SVG path data.
+struct PathData<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PathData<'a> {
+    fn skip_separators(&mut self) {
+        while let Some(byte) = self.bytes.get(self.position) {
+            if !(byte.is_ascii_whitespace() || *byte == b',') {
+                break;
+            }
+            self.position += 1;
+        }
+    }
+
+    fn command(&mut self) -> Result<Option<u8>, VectorError> {
+        self.skip_separators();
+        match self.bytes.get(self.position) {
+            None => Ok(None),
+            Some(byte) if byte.is_ascii_alphabetic() => {
+                self.position += 1;
+                Ok(Some(*byte))
+            }
+            Some(byte) => {
+                Err(self.error(&format!("expected a command, found '{}'", *byte as char)))
+            }
+        }
+    }
+
+    fn at_number(&mut self) -> bool {
+        self.skip_separators();
+        matches!(
+            self.bytes.get(self.position),
+            Some(b'0'..=b'9') | Some(b'.') | Some(b'-') | Some(b'+')
+        )
+    }
+
+    fn number(&mut self) -> Result<f32, VectorError> {
+        self.skip_separators();
+        let start = self.position;
+        let digits = |data: &mut Self| {
+            while matches!(data.bytes.get(data.position), Some(b'0'..=b'9')) {
+                data.position += 1;
+            }
+        };
+        if matches!(self.bytes.get(self.position), Some(b'-') | Some(b'+')) {
+            self.position += 1;
+        }
+        digits(self);
+        // A second dot starts the next number, as in "0.5.5"
+        if self.bytes.get(self.position) == Some(&b'.') {
+            self.position += 1;
+            digits(self);
+        }
+        if matches!(self.bytes.get(self.position), Some(b'e') | Some(b'E'))
+            && matches!(
+                self.bytes.get(self.position + 1),
+                Some(b'0'..=b'9') | Some(b'-') | Some(b'+')
+            )
+        {
+            self.position += 2;
+            digits(self);
+        }
+
+        std::str::from_utf8(&self.bytes[start..self.position])
+            .ok()
+            .and_then(|number| number.parse().ok())
+            .ok_or_else(|| self.error("expected a number"))
+    }
+
+    fn point(&mut self) -> Result<(f32, f32), VectorError> {
+        Ok((self.number()?, self.number()?))
+    }
+
+    /// Arc flags may be written without separators, as in "a1 1 0 011 1".
+    fn flag(&mut self) -> Result<bool, VectorError> {
+        self.skip_separators();
+        let flag = match self.bytes.get(self.position) {
+            Some(b'0') => false,
+            Some(b'1') => true,
+            _ => return Err(self.error("expected a flag")),
+        };
+        self.position += 1;
+
+        Ok(flag)
+    }
+
+    fn error(&self, message: &str) -> VectorError {
+        VectorError(format!(
+            "Invalid path data at position {}: {}",
+            self.position, message
+        ))
+    }
+}
+
+/// The numbers in a list like "0 0 100 50" or "1,2 3,4".
+fn numbers(value: &str) -> Vec<f32> {
+    value
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter_map(|number| number.parse().ok())
+        .collect()
+}
+
+/// A length in SVG pixels, `None` for percentages.
+fn length(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let (number, factor) = match value.find(|c: char| c.is_ascii_alphabetic() || c == '%') {
+        None => (value, 1.0),
+        Some(position) => {
+            let factor = match &value[position..] {
+                "px" => 1.0,
+                "in" => 96.0,
+                "cm" => 96.0 / 2.54,
+                "mm" => 96.0 / 25.4,
+                "pt" => 96.0 / 72.0,
+                "pc" => 16.0,
+                _ => return None,
+            };
+            (&value[..position], factor)
+        }
+    };
+
+    number
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .map(|number| number * factor)
+}
+
+/// The value of a `transform` attribute, like "translate(10 20) rotate(45)".
+fn parse_transform(value: &str) -> Result<Transform, VectorError> {
+    let mut transform = Transform::IDENTITY;
+    for part in value.split(')') {
+        let part = part.trim_matches(|c: char| c.is_whitespace() || c == ',');
+        if part.is_empty() {
+            continue;
+        }
+        let invalid = || VectorError(format!("Invalid transform '{}'", value));
+        let position = part.find('(').ok_or_else(invalid)?;
+        let arguments = numbers(&part[position + 1..]);
+        let next = match (part[..position].trim(), arguments.as_slice()) {
+            ("matrix", &[a, b, c, d, e, f]) => Transform::new(a, b, c, d, e, f),
+            ("translate", &[x]) => Transform::translate(x, 0.0),
+            ("translate", &[x, y]) => Transform::translate(x, y),
+            ("scale", &[s]) => Transform::scale(s, s),
+            ("scale", &[x, y]) => Transform::scale(x, y),
+            ("rotate", &[angle]) => Transform::rotate(angle),
+            ("rotate", &[angle, x, y]) => Transform::translate(x, y)
+                .multiply(&Transform::rotate(angle))
+                .multiply(&Transform::translate(-x, -y)),
+            ("skewX", &[angle]) => {
+                Transform::new(1.0, 0.0, angle.to_radians().tan(), 1.0, 0.0, 0.0)
+            }
+            ("skewY", &[angle]) => {
+                Transform::new(1.0, angle.to_radians().tan(), 0.0, 1.0, 0.0, 0.0)
+            }
+            _ => return Err(invalid()),
+        };
+        transform = transform.multiply(&next);
+    }
+
+    Ok(transform)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{dictionary, Stream};
+
+    fn lines(data: &str) -> Vec<Vec<(f32, f32)>> {
+        let mut path = PathBuilder::new(Transform::IDENTITY);
+        parse_path(data, &mut path).unwrap();
+
+        path.take()
+    }
+
+    fn assert_near((x, y): (f32, f32), (expected_x, expected_y): (f32, f32)) {
+        assert!(
+            (x - expected_x).abs() < 0.01 && (y - expected_y).abs() < 0.01,
+            "({}, {}) is not ({}, {})",
+            x,
+            y,
+            expected_x,
+            expected_y
+        );
+    }
+
+    #[test]
+    fn coordinates_after_a_move_are_lines() {
+        assert_eq!(
+            lines("M0 0 10 0 10 10"),
+            vec![vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]]
+        );
+        assert_eq!(
+            lines("m1 1 2 2l1 0 0 1"),
+            vec![vec![(1.0, 1.0), (3.0, 3.0), (4.0, 3.0), (4.0, 4.0)]]
+        );
+        assert_eq!(
+            lines("M0 0H5V5h-5z"),
+            vec![vec![
+                (0.0, 0.0),
+                (5.0, 0.0),
+                (5.0, 5.0),
+                (0.0, 5.0),
+                (0.0, 0.0)
+            ]]
+        );
+    }
+
+    #[test]
+    fn numbers_need_no_separators() {
+        assert_eq!(lines("M0.5.5L-1-2"), vec![vec![(0.5, 0.5), (-1.0, -2.0)]]);
+        assert_eq!(lines("M1e1,2E-1L3 4"), vec![vec![(10.0, 0.2), (3.0, 4.0)]]);
+        assert!(parse_path("M0 0L1", &mut PathBuilder::new(Transform::IDENTITY)).is_err());
+    }
+
+    #[test]
+    fn arcs_end_exactly_at_their_endpoint() {
+        // Packed flags: large arc 0, sweep 1
+        let arc = lines("M0 0a1 1 0 011 1");
+        assert_eq!(arc.len(), 1);
+        assert_eq!(arc[0].first(), Some(&(0.0, 0.0)));
+        assert_eq!(arc[0].last(), Some(&(1.0, 1.0)));
+        for point in &arc[0] {
+            assert!((distance(*point, (0.0, 1.0)) - 1.0).abs() < 0.01);
+        }
+
+        // Following relative commands start at the endpoint
+        let arc = lines("M10 10a5 5 0 0 1 10 0l0 5");
+        assert_eq!(arc[0].last(), Some(&(20.0, 15.0)));
+
+        // Radii too small for the endpoints are scaled up to a half circle
+        let arc = lines("M0 0A1 1 0 0 1 10 0");
+        assert_eq!(arc[0].last(), Some(&(10.0, 0.0)));
+        assert_near(arc[0][arc[0].len() / 2], (5.0, -5.0));
+
+        assert_eq!(
+            lines("M0 0A0 5 0 0 1 10 0"),
+            vec![vec![(0.0, 0.0), (10.0, 0.0)]]
+        );
+    }
+
+    #[test]
+    fn transforms_apply_from_right_to_left() {
+        let transform = parse_transform("translate(10 20) scale(2)").unwrap();
+        assert_near(transform.apply((1.0, 1.0)), (12.0, 22.0));
+        assert_near(
+            parse_transform("rotate(90)").unwrap().apply((1.0, 0.0)),
+            (0.0, 1.0),
+        );
+        assert_near(
+            parse_transform("rotate(90 10 10)")
+                .unwrap()
+                .apply((20.0, 10.0)),
+            (10.0, 20.0),
+        );
+        assert_near(
+            parse_transform("matrix(1,0,0,1,5,6)")
+                .unwrap()
+                .apply((0.0, 0.0)),
+            (5.0, 6.0),
+        );
+        assert!(parse_transform("shear(1)").is_err());
+        assert!(parse_transform("scale(1 2 3)").is_err());
+    }
+
+    #[test]
+    fn pdf_paths_are_scaled_to_device_pixels() {
+        let mut document = lopdf::Document::with_version("1.5");
+        let pages_id = document.new_object_id();
+        let content = Stream::new(
+            dictionary! {},
+            b"1 0 0 1 10 10 cm 0 0 m 36 0 l S 0 0 m 1 1 l n".to_vec(),
+        );
+        let content_id = document.add_object(content);
+        let page_id = document.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => content_id,
+        });
+        document.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+                "MediaBox" => vec![0.into(), 0.into(), 72.into(), 72.into()],
+            }),
+        );
+        let catalog_id = document.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        document.trailer.set("Root", catalog_id);
+        let mut data = vec![];
+        document.save_to(&mut data).unwrap();
+
+        let pages = pdf_strokes(&data, &StrokeStyle::default()).unwrap();
+        assert_eq!(pages.len(), 1);
+        // The clipping path is skipped
+        assert_eq!(pages[0].len(), 1);
+        let segments = &pages[0][0].segments;
+        assert_eq!(segments.len(), 2);
+        // One inch is 226 pixels, and y grows downwards from the top of the 1 inch page
+        let pixels = DPI / 72.0;
+        assert_near(
+            (segments[0].x, segments[0].y),
+            (10.0 * pixels, 62.0 * pixels),
+        );
+        assert_near(
+            (segments[1].x, segments[1].y),
+            (46.0 * pixels, 62.0 * pixels),
+        );
+        assert_eq!(segments[1].width, StrokeStyle::default().width);
+    }
+}
@@ -0,0 +1,145 @@
+// Following changes in the xochitl directory.
+
+use notify::{DebouncedEvent, PollWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+/// How long a file must stay unchanged before its events are reported, and how often files are
+/// checked when polling.
+const DELAY: Duration = Duration::from_secs(2);
+
+/// Changes are handled once nothing changed for this long, so a sync that writes many files
+/// leads to one update.
+const QUIET_PERIOD: Duration = Duration::from_secs(5);
+
+/// Call `on_change` with `None` for all documents, then with the IDs of the documents and
+/// folders whose pages, content or metadata changed in `directory`, until watching fails.
+pub fn watch(
+    directory: &Path,
+    poll: bool,
+    on_change: &mut dyn FnMut(Option<&HashSet<String>>),
+) -> Result<(), Box<dyn Error>> {
+    let (sender, receiver) = channel();
+    // Keep the watcher alive while waiting for events
+    let _watcher: Box<dyn std::any::Any> = if poll {
+        let mut watcher = PollWatcher::new(sender, DELAY)?;
+        watcher.watch(directory, RecursiveMode::Recursive)?;
+        Box::new(watcher)
+    } else {
+        let mut watcher = notify::watcher(sender, DELAY)?;
+        watcher.watch(directory, RecursiveMode::Recursive)?;
+        Box::new(watcher)
+    };
+
+    on_change(None);
+    println!("Watching {:?} for changes...", directory);
+    let root = directory.canonicalize()?;
+    loop {
+        let changed: HashSet<String> = wait_for_changes(&receiver)?
+            .iter()
+            .filter_map(|path| {
+                path.strip_prefix(&root)
+                    .or_else(|_| path.strip_prefix(directory))
+                    .ok()
+            })
+            .filter_map(changed_document)
+            .collect();
+        if !changed.is_empty() {
+            on_change(Some(&changed));
+        }
+    }
+}
+
+/// Paths of the files changed in the next burst of changes.
+fn wait_for_changes(receiver: &Receiver<DebouncedEvent>) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut paths = vec![];
+    let mut event = receiver.recv()?;
+    loop {
+        match event {
+            DebouncedEvent::Create(path)
+            | DebouncedEvent::Write(path)
+            | DebouncedEvent::Remove(path) => paths.push(path),
+            DebouncedEvent::Rename(from, to) => {
+                paths.push(from);
+                paths.push(to);
+            }
+            DebouncedEvent::Error(e, path) => {
+                eprintln!("WARNING: Failed to watch {:?}: {}", path, e)
+            }
+            _ => {}
+        }
+        event = match receiver.recv_timeout(QUIET_PERIOD) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => return Ok(paths),
+            Err(e) => return Err(Box::new(e)),
+        };
+    }
+}
+
+/// The document that a changed file belongs to: `<id>.content`, `<id>.metadata` or a page
+/// `<id>/<page>.rm`. Other files, like thumbnails, do not change what is rendered.
+fn changed_document(path: &Path) -> Option<String> {
+    let components: Vec<_> = path
+        .components()
+        .map(|component| component.as_os_str().to_str())
+        .collect::<Option<_>>()?;
+    match components.as_slice() {
+        [file] => file
+            .strip_suffix(".content")
+            .or_else(|| file.strip_suffix(".metadata"))
+            .map(str::to_owned),
+        [id, page] if page.ends_with(".rm") => Some((*id).to_owned()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_and_metadata_belong_to_their_document() {
+        assert_eq!(
+            changed_document(Path::new("abc.content")),
+            Some("abc".to_owned())
+        );
+        assert_eq!(
+            changed_document(Path::new("abc.metadata")),
+            Some("abc".to_owned())
+        );
+    }
+
+    #[test]
+    fn pages_belong_to_their_document() {
+        assert_eq!(
+            changed_document(Path::new("abc/0b5c3f5e.rm")),
+            Some("abc".to_owned())
+        );
+    }
+
+    #[test]
+    fn other_files_are_ignored() {
+        assert_eq!(changed_document(Path::new("abc.pagedata")), None);
+        assert_eq!(changed_document(Path::new("abc.thumbnails/0.jpg")), None);
+        assert_eq!(
+            changed_document(Path::new("abc/0b5c3f5e-metadata.json")),
+            None
+        );
+        assert_eq!(changed_document(Path::new("abc/deeper/0b5c3f5e.rm")), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_paths_are_ignored() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new(OsStr::from_bytes(b"ab\xff.content"));
+        assert_eq!(changed_document(path), None);
+        let path = Path::new(OsStr::from_bytes(b"ab\xff/0b5c3f5e.rm"));
+        assert_eq!(changed_document(path), None);
+    }
+}
@@ -0,0 +1,106 @@
+// Reading tests/fixtures/xochitl from a local SSH server, as started by .github/workflows/ci.yml.
+// The tests are ignored unless asked for, with the server given in the environment:
+//
+//     RESTORABLE_TEST_SSH_HOST=127.0.0.1:2222 RESTORABLE_TEST_SSH_KEY=/path/to/id_ed25519 \
+//         cargo test --no-default-features --features sftp --test sftp -- --ignored
+
+#![cfg(feature = "sftp")]
+
+use std::env;
+use std::path::PathBuf;
+
+use restorable::node::{parse_nodes, ParseOptions};
+use restorable::notebook::Notebook;
+use restorable::selector::select_one;
+use restorable::sftp::{HostKeyError, KnownHosts, Sftp};
+use restorable::source::Source;
+
+const OTHER_KEY: &str =
+    "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAINsABq2l6Uz3yicQPYtaWTt64C8Bevk6a+vgDogEGC8F";
+
+fn host() -> String {
+    env::var("RESTORABLE_TEST_SSH_HOST").expect("RESTORABLE_TEST_SSH_HOST is not set")
+}
+
+/// A known hosts file that does not exist yet.
+fn known_hosts(name: &str, accept_new: bool) -> KnownHosts {
+    let directory = env::temp_dir().join(format!("restorable-sftp-{}", name));
+    let _ = std::fs::remove_dir_all(&directory);
+
+    KnownHosts {
+        path: directory.join("known_hosts"),
+        accept_new,
+    }
+}
+
+fn connect(known_hosts: &KnownHosts) -> Result<Sftp, Box<dyn std::error::Error>> {
+    let user = env::var("RESTORABLE_TEST_SSH_USER")
+        .or_else(|_| env::var("USER"))
+        .expect("RESTORABLE_TEST_SSH_USER is not set");
+    let key = env::var_os("RESTORABLE_TEST_SSH_KEY").map(PathBuf::from);
+    let root = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/xochitl");
+
+    Sftp::connect(&host(), &user, key.as_deref(), root, known_hosts)
+}
+
+#[test]
+#[ignore]
+fn unknown_hosts_are_rejected() {
+    let known_hosts = known_hosts("unknown", false);
+    let error = connect(&known_hosts)
+        .err()
+        .expect("connected to an unknown host");
+
+    assert!(matches!(
+        error.downcast_ref::<HostKeyError>(),
+        Some(HostKeyError::Unknown { .. })
+    ));
+    assert!(!known_hosts.path.exists());
+}
+
+#[test]
+#[ignore]
+fn changed_host_keys_are_rejected() {
+    let known_hosts = known_hosts("changed", false);
+    let host = host();
+    let (name, port) = host.split_at(host.rfind(':').expect("the host has no port"));
+    std::fs::create_dir_all(known_hosts.path.parent().unwrap()).unwrap();
+    std::fs::write(
+        &known_hosts.path,
+        format!("[{}]{} {}\n", name, port, OTHER_KEY),
+    )
+    .unwrap();
+    let error = connect(&known_hosts)
+        .err()
+        .expect("connected with a changed key");
+
+    assert!(matches!(
+        error.downcast_ref::<HostKeyError>(),
+        Some(HostKeyError::Mismatch { .. })
+    ));
+}
+
+#[test]
+#[ignore]
+fn accepted_hosts_are_remembered() {
+    let mut known_hosts = known_hosts("accepted", true);
+    connect(&known_hosts).unwrap();
+    let written = std::fs::read_to_string(&known_hosts.path).unwrap();
+    assert_eq!(written.lines().count(), 1);
+
+    known_hosts.accept_new = false;
+    let sftp = connect(&known_hosts).unwrap();
+    assert_eq!(std::fs::read_to_string(&known_hosts.path).unwrap(), written);
+
+    let root = parse_nodes(&sftp, &ParseOptions::default()).unwrap();
+    let node = select_one(&root, "Work/Notes").unwrap();
+    let notebook = Notebook::load(&sftp, &node.id).unwrap();
+    assert_eq!(notebook.pages.len(), 1);
+    let strokes = &notebook.pages[0].linefile.layers[0].strokes;
+    assert_eq!(strokes.len(), 1);
+    assert_eq!(
+        (strokes[0].segments[1].x, strokes[0].segments[1].y),
+        (300.0, 400.0)
+    );
+    assert!(sftp.modified(&format!("{}.metadata", node.id)).is_ok());
+}